@@ -1,5 +1,10 @@
 #![allow(unused)]
-use std::fmt::Display;
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 #[derive(Clone, Debug)]
 pub enum Error {
@@ -15,59 +20,70 @@ impl Display for Error {
 }
 type Result<T> = ::std::result::Result<T, Error>;
 
-pub fn run(name: &str, args: &[String]) -> Result<i32> {
+/// ビルトインを実行する
+///
+/// 出力は`stdout`と`stderr`に書き込む
+/// 相対パスは`cwd`から辿り、`cd`は`cwd`を変更する
+pub fn run(
+    name: &str,
+    args: &[OsString],
+    cwd: &mut PathBuf,
+    home: Option<&Path>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
     match name {
-        "cd" => cd(args),
+        "cd" => cd(args, cwd, home),
         "exit" => exit(args),
-        "mkdir" => mkdir(args),
+        "mkdir" => mkdir(args, cwd, stderr),
         _ => Err(Error::CommandNotFound),
     }
 }
-fn cd(args: &[String]) -> Result<i32> {
+fn cd(
+    args: &[OsString],
+    cwd: &mut PathBuf,
+    home: Option<&Path>,
+) -> Result<i32> {
     if 1 < args.len() {
         return Err(Error::InvalidArgs);
     }
 
-    if let Some(dir) = args.first() {
-        let next_dir = std::env::current_dir()
-            .map_err(|_| {
-                Error::Runtime(
-                    "現在のディレクトリが見つかりませんでした".to_string(),
-                )
-            })?
-            .join(dir);
-        if next_dir.exists() && next_dir.is_dir() {
-            std::env::set_current_dir(next_dir).map_err(|_| {
-                Error::Runtime("ディレクトリの移動に失敗しました".to_string())
-            })?;
-        }
-        else {
+    let next_dir = if let Some(dir) = args.first() {
+        let next_dir = cwd.join(dir);
+        if !(next_dir.exists() && next_dir.is_dir()) {
             return Err(Error::InvalidArgs);
         }
+        next_dir
     }
     else {
-        let home_dir = dirs::home_dir().ok_or(Error::Runtime(
+        home.ok_or(Error::Runtime(
             "ホームディレクトリの取得に失敗しました".to_string(),
-        ))?;
-        std::env::set_current_dir(home_dir).map_err(|_| {
-            Error::Runtime("ディレクトリの移動に失敗しました".to_string())
-        })?;
-    }
+        ))?
+        .to_path_buf()
+    };
+    // `..`などを解決しておく
+    *cwd = next_dir.canonicalize().map_err(|_| {
+        Error::Runtime("ディレクトリの移動に失敗しました".to_string())
+    })?;
     Ok(0)
 }
-fn exit(args: &[String]) -> Result<i32> {
+fn exit(args: &[OsString]) -> Result<i32> {
     if 1 < args.len() {
         return Err(Error::InvalidArgs);
     }
 
     let code = args
         .first()
-        .map(|code| code.parse::<i32>().map_err(|_| Error::InvalidArgs))
+        .map(|code| {
+            code.to_str()
+                .and_then(|code| code.parse::<i32>().ok())
+                .ok_or(Error::InvalidArgs)
+        })
         .transpose()?
         .unwrap_or(0);
     Err(Error::Exit(code))
 }
-fn mkdir(args: &[String]) -> Result<i32> {
+fn mkdir(args: &[OsString], cwd: &Path, stderr: &mut dyn Write) -> Result<i32> {
     if args.is_empty() {
         return Err(Error::InvalidArgs);
     }
@@ -75,11 +91,11 @@ fn mkdir(args: &[String]) -> Result<i32> {
     let mut exit_status = 0;
     for dir in args {
         use std::io::ErrorKind;
-        match std::fs::create_dir_all(dir) {
+        match std::fs::create_dir_all(cwd.join(dir)) {
             Ok(_) => {}
             Err(e) => {
                 exit_status = 1;
-                eprintln!("{e}");
+                let _ = writeln!(stderr, "{e}");
             }
         }
     }
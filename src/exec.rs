@@ -1,38 +1,639 @@
 #![allow(unused)]
-use crate::parse::ShellCommand;
-use std::{ffi::OsString, fmt::Display, path::PathBuf};
+mod check;
+mod eval;
+mod executable;
+mod expand;
+mod function;
+mod stream;
+mod var;
 
+use crate::{
+    parse::{
+        Block, Condition, ForIter, If, Loop, LoopKind, Match, Pattern, Pipe,
+        Redirect, RedirectTarget, ShellCommand, Spanned, Statement, Word,
+        error::Source,
+    },
+    value::{Type, Value},
+};
+pub use eval::{EvalError, EvalErrorKind};
+use executable::CommandTable;
+use function::FunctionDef;
+pub use function::STACK_SIZE;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::Scope,
+};
+use stream::{Stream, Streams};
+use var::Var;
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug)]
 pub enum Error {
     Exit(i32),
+    /// ラベルが指定されていればそのループを抜ける
+    Break(Option<String>),
+    Continue(Option<String>),
+    /// 関数から終了ステータスを返す
+    Return(i32),
+    /// Ctrl-Cによる中断
+    Interrupted,
+    CommandNotFound(String),
     CommandError(String),
+    Eval(EvalError),
+}
+impl Error {
+    /// エラーで終了したコマンドの終了ステータス
+    pub fn status(&self) -> i32 {
+        match self {
+            Error::Exit(code) | Error::Return(code) => *code,
+            Error::Break(_) | Error::Continue(_) => 0,
+            Error::Interrupted => 130,
+            Error::CommandNotFound(_) => 127,
+            Error::CommandError(_) | Error::Eval(_) => 1,
+        }
+    }
+    /// 表示せずに呼び出し元に返すエラー
+    fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            Error::Exit(_)
+                | Error::Break(_)
+                | Error::Continue(_)
+                | Error::Return(_)
+                | Error::Interrupted
+        )
+    }
+    /// エラー箇所を含めて表示する
+    pub fn display<'a>(&self, source: impl Into<Source<'a>>) -> String {
+        match self {
+            Error::Eval(e) => e.display(source),
+            e => format!("コマンドの実行に失敗しました : {e}\n"),
+        }
+    }
 }
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Error::Exit(code) => write!(f, "exit {code}"),
+            Error::Break(_) => write!(f, "ループの外でbreakは使えません"),
+            Error::Continue(_) => {
+                write!(f, "ループの外でcontinueは使えません")
+            }
+            Error::Return(_) => write!(f, "関数の外でreturnは使えません"),
+            Error::Interrupted => write!(f, "中断しました"),
+            Error::CommandNotFound(name) => {
+                write!(f, "コマンド '{name}' が見つかりませんでした")
+            }
+            Error::CommandError(message) => write!(f, "{message}"),
+            Error::Eval(e) => write!(f, "{}", e.kind),
+        }
+    }
+}
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Self {
+        Error::Eval(e)
     }
 }
 type Result<T> = ::std::result::Result<T, Error>;
 
-#[derive(Clone, Debug, Default)]
+/// Ctrl-Cが押されたか
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Ctrl-Cでシェルを終了せず、実行中のコマンドの後を中断するようにする
+pub fn handle_interrupt() -> ::std::result::Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+}
+/// Ctrl-Cが押されていれば`Error::Interrupted`を返す
+fn check_interrupt() -> Result<()> {
+    if INTERRUPTED.swap(false, Ordering::SeqCst) {
+        Err(Error::Interrupted)
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Shell {
     last_status: Option<i32>,
+    last_background_pid: Option<u32>,
+    shell_name: String,
+    vars: HashMap<String, Var>,
+    functions: HashMap<String, Arc<FunctionDef>>,
+    /// 実行中の関数の呼び出しの深さ
+    call_depth: usize,
+    /// 実行するコマンドの標準入出力
+    ///
+    /// 関数の中ではパイプやリダイレクトの接続先になる
+    io: Arc<Streams>,
+    /// シェルのカレントディレクトリ
+    ///
+    /// パイプラインの段を同時に実行できるよう、プロセスのカレントディレクトリは変更しない
+    cwd: PathBuf,
+    /// シェルで変更した環境変数（削除した変数は`None`）
+    ///
+    /// プロセスの環境変数は変更せず、外部コマンドの起動時に渡す
+    env: HashMap<String, Option<OsString>>,
+    commands: CommandTable,
+}
+impl Default for Shell {
+    fn default() -> Self {
+        Self {
+            last_status: None,
+            last_background_pid: None,
+            shell_name: "asari".to_string(),
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+            call_depth: 0,
+            io: Arc::new(Streams::default()),
+            // 取得できなければプロセスのカレントディレクトリからの相対パスになる
+            cwd: std::env::current_dir().unwrap_or_default(),
+            env: HashMap::new(),
+            commands: CommandTable::default(),
+        }
+    }
 }
 impl Shell {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn execute(&mut self, cmd: &ShellCommand) -> Result<()> {
+    /// スクリプトとして実行する
+    ///
+    /// `$@`をスクリプト名にし、引数を`%args`に設定する
+    pub fn set_script(&mut self, name: String, args: Vec<String>) {
+        self.shell_name = name;
+        self.set_args(args);
+    }
+    /// 引数を`%args`に設定する
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.vars.insert(
+            "args".to_string(),
+            Var {
+                ty: Type::Array(Box::new(Type::String)),
+                value: Value::Array(
+                    args.into_iter().map(Value::String).collect(),
+                ),
+            },
+        );
+    }
+    /// シェルのカレントディレクトリ
+    pub fn current_dir(&self) -> &Path {
+        &self.cwd
+    }
+    /// 直前のコマンドの終了ステータス
+    pub fn last_status(&self) -> i32 {
+        self.last_status.unwrap_or(0)
+    }
+    /// 型を検査してからコマンドを実行する
+    ///
+    /// 型エラーがあれば何も実行しない
+    /// `exit`とCtrl-Cによる中断は`Err`で返す
+    /// `source`はエラー表示に使う
+    pub fn execute<'a>(
+        &mut self,
+        cmd: &ShellCommand,
+        source: impl Into<Source<'a>>,
+    ) -> Result<()> {
+        let source = source.into();
+        // 実行前に全体の型を検査する
+        if let Err(e) = self.check(cmd) {
+            eprint!("{}", e.display(source));
+            self.last_status = Some(1);
+            return Ok(());
+        }
+        // 実行前に押されたCtrl-Cは無視する
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        match self.execute_list(&cmd.commands, source) {
+            Err(Error::Interrupted) => {
+                self.last_status = Some(Error::Interrupted.status());
+                Err(Error::Interrupted)
+            }
+            result => result,
+        }
+    }
+    /// `;`や改行で区切られたパイプラインを順に実行する
+    ///
+    /// 失敗したパイプラインがあってもエラーを表示して実行を続ける
+    fn execute_list(
+        &mut self,
+        commands: &[(Statement, Option<Pipe>)],
+        source: Source,
+    ) -> Result<()> {
+        let mut pipeline = Vec::new();
+        for (command, pipe) in commands {
+            pipeline.push(command);
+            if matches!(pipe, Some(Pipe::Pipe)) {
+                continue;
+            }
+            let status = self.run_pipeline(&pipeline, source)?;
+            self.last_status = Some(status);
+            pipeline.clear();
+            check_interrupt()?;
+        }
+        Ok(())
+    }
+    /// パイプラインを実行して終了ステータスを返す
+    ///
+    /// `exit`や`break`以外のエラーは表示して、そのエラーの終了ステータスを返す
+    fn run_pipeline(
+        &mut self,
+        pipeline: &[&Statement],
+        source: Source,
+    ) -> Result<i32> {
+        match self.execute_pipeline(pipeline, source) {
+            Ok(status) => Ok(status),
+            Err(e) if e.is_control_flow() => Err(e),
+            Err(e) => {
+                eprint!("{}", e.display(source));
+                Ok(e.status())
+            }
+        }
+    }
+    /// パイプラインを実行して最後のコマンドの終了ステータスを返す
+    fn execute_pipeline(
+        &mut self,
+        pipeline: &[&Statement],
+        source: Source,
+    ) -> Result<i32> {
+        // 別スレッドで実行した段はスコープの終わりで待つ
+        let (result, mut statuses) = std::thread::scope(|scope| {
+            let mut children = Vec::new();
+            let result =
+                self.spawn_pipeline(pipeline, source, scope, &mut children);
+            // 起動済みの子プロセスは失敗時も必ず待つ
+            let mut statuses = Vec::new();
+            for child in &mut children {
+                statuses.push(child.wait());
+            }
+            (result, statuses)
+        });
+        let last = result?;
+        match last {
+            Stage::Builtin(status) => Ok(status),
+            Stage::External => match statuses.pop() {
+                Some(Ok(status)) => Ok(exit_code(status)),
+                Some(Err(e)) => Err(Error::CommandError(e.to_string())),
+                None => unreachable!(),
+            },
+        }
+    }
+    /// 条件が真になった最初のブロックを実行して終了ステータスを返す
+    ///
+    /// どのブロックも実行しなければ0
+    fn execute_if(&mut self, if_: &If, source: Source) -> Result<i32> {
+        for (condition, block) in &if_.branches {
+            if self.test_condition(condition, source)? {
+                return self.execute_block(block, source);
+            }
+        }
+        match &if_.else_block {
+            Some(block) => self.execute_block(block, source),
+            None => Ok(0),
+        }
+    }
+    fn test_condition(
+        &mut self,
+        condition: &Condition,
+        source: Source,
+    ) -> Result<bool> {
+        match condition {
+            Condition::Expr(word) => match self.eval_word(word)? {
+                Value::Bool(bool) => Ok(bool),
+                value => {
+                    Err(eval::type_mismatch(word.span(), "bool", &value).into())
+                }
+            },
+            // ブロックの中の$?は条件のコマンドの終了ステータスになる
+            Condition::Command(pipeline) => {
+                let pipeline: Vec<_> = pipeline.iter().collect();
+                let status = self.run_pipeline(&pipeline, source)?;
+                self.last_status = Some(status);
+                Ok(status == 0)
+            }
+        }
+    }
+    /// ループを実行して最後に実行したブロックの終了ステータスを返す
+    fn execute_loop(&mut self, loop_: &Loop, source: Source) -> Result<i32> {
+        match &loop_.kind {
+            LoopKind::While(condition) => {
+                let mut status = 0;
+                while self.test_condition(condition, source)? {
+                    match self.execute_body(loop_, source)? {
+                        Some(body) => status = body,
+                        None => break,
+                    }
+                }
+                Ok(status)
+            }
+            LoopKind::For { var, iter } => {
+                let values = self.eval_for_iter(iter)?;
+                // ループ変数はループの中だけで使える
+                let saved = self.vars.remove(var.inner());
+                let result =
+                    self.execute_for(loop_, var.inner(), values, source);
+                match saved {
+                    Some(saved) => self.vars.insert(var.inner().clone(), saved),
+                    None => self.vars.remove(var.inner()),
+                };
+                result
+            }
+        }
+    }
+    fn execute_for(
+        &mut self,
+        loop_: &Loop,
+        var: &str,
+        values: impl Iterator<Item = Value>,
+        source: Source,
+    ) -> Result<i32> {
+        let mut status = 0;
+        for value in values {
+            self.vars.insert(var.to_string(), value.into());
+            match self.execute_body(loop_, source)? {
+                Some(body) => status = body,
+                None => break,
+            }
+        }
+        Ok(status)
+    }
+    /// ループのブロックを1回実行して終了ステータスを返す
+    ///
+    /// このループを抜ける`break`なら`None`を返す
+    fn execute_body(
+        &mut self,
+        loop_: &Loop,
+        source: Source,
+    ) -> Result<Option<i32>> {
+        check_interrupt()?;
+        let is_target =
+            |label: &Option<String>| label.is_none() || *label == loop_.label;
+        match self.execute_block(&loop_.block, source) {
+            Ok(status) => Ok(Some(status)),
+            Err(Error::Break(label)) if is_target(&label) => Ok(None),
+            Err(Error::Continue(label)) if is_target(&label) => Ok(Some(0)),
+            Err(e) => Err(e),
+        }
+    }
+    /// `for`で繰り返す値
+    fn eval_for_iter(
+        &self,
+        iter: &ForIter,
+    ) -> Result<Box<dyn Iterator<Item = Value>>> {
+        match iter {
+            ForIter::Word(word) => match self.eval_word(word)? {
+                Value::Array(values) => Ok(Box::new(values.into_iter())),
+                value => {
+                    Err(eval::type_mismatch(word.span(), "array", &value)
+                        .into())
+                }
+            },
+            ForIter::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.eval_int(start)?;
+                let end = self.eval_int(end)?;
+                Ok(if *inclusive {
+                    Box::new((start..=end).map(Value::Int))
+                }
+                else {
+                    Box::new((start..end).map(Value::Int))
+                })
+            }
+        }
+    }
+    /// 最初に一致した腕を実行して終了ステータスを返す
+    ///
+    /// どの腕にも一致しなければ0を返す
+    fn execute_match(&mut self, match_: &Match, source: Source) -> Result<i32> {
+        let value = self.eval_word(&match_.value)?;
+        for arm in &match_.arms {
+            let Some(bindings) = arm
+                .patterns
+                .iter()
+                .find_map(|pattern| match_pattern(pattern.inner(), &value))
+            else {
+                continue;
+            };
+            // 束縛した変数は腕の中だけで使える
+            let saved: Vec<_> = bindings
+                .iter()
+                .map(|(name, _)| (name.clone(), self.vars.get(name).cloned()))
+                .collect();
+            for (name, value) in bindings {
+                self.vars.insert(name, value.into());
+            }
+            let result = self.execute_block(&arm.block, source);
+            for (name, saved) in saved {
+                match saved {
+                    Some(saved) => self.vars.insert(name, saved),
+                    None => self.vars.remove(&name),
+                };
+            }
+            return result;
+        }
+        Ok(0)
+    }
+    /// ブロックをサブシェルで実行して終了ステータスを返す
+    ///
+    /// 変数・環境変数・カレントディレクトリの変更は元に戻す
+    fn execute_subshell(&self, block: &Block, source: Source) -> Result<i32> {
+        self.clone()
+            .into_subshell(|shell| shell.execute_block(block, source))
+    }
+    /// このシェルをサブシェルとして`f`を実行する
+    ///
+    /// 変更はすべてこのシェルの中に留まる
+    fn into_subshell(
+        mut self,
+        f: impl FnOnce(&mut Self) -> Result<i32>,
+    ) -> Result<i32> {
+        match f(&mut self) {
+            // exitはサブシェルだけを終了する
+            Err(Error::Exit(code)) => Ok(code),
+            result => result,
+        }
+    }
+    /// ブロックを実行して最後のパイプラインの終了ステータスを返す
+    fn execute_block(&mut self, block: &Block, source: Source) -> Result<i32> {
+        if block.commands.is_empty() {
+            return Ok(0);
+        }
+        self.execute_list(&block.commands, source)?;
+        Ok(self.last_status())
+    }
+    fn spawn_pipeline<'scope>(
+        &mut self,
+        pipeline: &[&'scope Statement],
+        source: Source<'scope>,
+        scope: &'scope Scope<'scope, '_>,
+        children: &mut Vec<Child>,
+    ) -> Result<Stage> {
         use crate::builtin::Error as BuiltinError;
-        use crate::parse::Word;
-        for (command, _pipe) in &cmd.commands {
-            let name = command.name.to_string();
-            let args: Vec<_> =
-                command.args.iter().map(ToString::to_string).collect();
+        let mut stdin = self.io.stdin.try_clone().map_err(io_error)?;
+        let mut last = Stage::Builtin(0);
+        for (i, statement) in pipeline.iter().enumerate() {
+            let is_last = i + 1 == pipeline.len();
+            // 代入やブロックの出力は次のコマンドに渡さないので
+            // 次のコマンドの入力は空になる
+            let command = match statement {
+                Statement::Command(command) => command,
+                Statement::Assign(assign) => {
+                    self.assign(assign)?;
+                    if !is_last {
+                        stdin = empty_stream()?;
+                    }
+                    last = Stage::Builtin(0);
+                    continue;
+                }
+                Statement::If(if_) => {
+                    last = self.block_stage(
+                        scope,
+                        source,
+                        &mut stdin,
+                        is_last,
+                        move |shell| shell.execute_if(if_, source),
+                    )?;
+                    continue;
+                }
+                Statement::Loop(loop_) => {
+                    last = self.block_stage(
+                        scope,
+                        source,
+                        &mut stdin,
+                        is_last,
+                        move |shell| shell.execute_loop(loop_, source),
+                    )?;
+                    continue;
+                }
+                Statement::Match(match_) => {
+                    last = self.block_stage(
+                        scope,
+                        source,
+                        &mut stdin,
+                        is_last,
+                        move |shell| shell.execute_match(match_, source),
+                    )?;
+                    continue;
+                }
+                Statement::Group(group) => {
+                    let streams = self.stage_streams(
+                        &mut stdin,
+                        is_last,
+                        &group.redirects,
+                    )?;
+                    if !is_last {
+                        self.spawn_stage(scope, source, move |shell| {
+                            shell.with_io(streams, |shell| {
+                                shell.execute_block(&group.block, source)
+                            })
+                        })?;
+                        continue;
+                    }
+                    let status = self.with_io(streams, |shell| {
+                        if group.subshell {
+                            shell.execute_subshell(&group.block, source)
+                        }
+                        else {
+                            shell.execute_block(&group.block, source)
+                        }
+                    })?;
+                    last = Stage::Builtin(status);
+                    continue;
+                }
+                Statement::Break(label) => {
+                    return Err(Error::Break(label.inner().clone()));
+                }
+                Statement::Continue(label) => {
+                    return Err(Error::Continue(label.inner().clone()));
+                }
+                Statement::Function(function) => {
+                    self.define_function(function, source);
+                    if !is_last {
+                        stdin = empty_stream()?;
+                    }
+                    last = Stage::Builtin(0);
+                    continue;
+                }
+                Statement::Return(value) => {
+                    return Err(Error::Return(
+                        self.return_status(value.inner())?,
+                    ));
+                }
+            };
+            let env = self.eval_temp_env(&command.env)?;
+            let mut args = Vec::new();
+            let mut arg_spans = Vec::new();
+            for word in std::iter::once(&command.name).chain(&command.args) {
+                let expanded = self.expand_word(word)?;
+                arg_spans.extend(expanded.iter().map(|_| word.span()));
+                args.extend(expanded);
+            }
+            if args.is_empty() {
+                return Err(Error::Eval(EvalError {
+                    kind: EvalErrorKind::EmptyCommand,
+                    span: command.name.span(),
+                }));
+            }
+            let name = args.remove(0);
+            let builtin_name = name.to_string_lossy();
+
+            let streams =
+                self.stage_streams(&mut stdin, is_last, &command.redirects)?;
+
+            // 関数の呼び出しを試す
+            if let Some(def) =
+                self.functions.get(builtin_name.as_ref()).cloned()
+            {
+                let args: Vec<_> =
+                    args.into_iter().zip(arg_spans.drain(1..)).collect();
+                let span = command.name.span();
+                let call = move |shell: &mut Self| {
+                    shell.with_env(&env, |shell| {
+                        shell.call_function(&def, args, span, streams)
+                    })
+                };
+                if is_last {
+                    last = Stage::Builtin(call(self)?);
+                }
+                else {
+                    self.spawn_stage(scope, source, call)?;
+                }
+                continue;
+            }
 
             // ビルトインの実行を試す
-            match crate::builtin::run(&name, &args) {
-                Ok(_) => {
+            let mut stdout = Vec::new();
+            let mut stderr = Vec::new();
+            let result = self.with_env(&env, |shell| {
+                let home = shell.home_dir();
+                crate::builtin::run(
+                    &builtin_name,
+                    &args,
+                    &mut shell.cwd,
+                    home.as_deref(),
+                    &mut stdout,
+                    &mut stderr,
+                )
+            });
+            match result {
+                Ok(status) => {
+                    streams.stdout.write_output(stdout).map_err(io_error)?;
+                    streams.stderr.write_output(stderr).map_err(io_error)?;
+                    last = Stage::Builtin(status);
+                    continue;
+                }
+                // パイプラインの中のexitはその段だけを終了する
+                Err(BuiltinError::Exit(code)) if 1 < pipeline.len() => {
+                    last = Stage::Builtin(code);
                     continue;
                 }
                 Err(BuiltinError::Exit(code)) => return Err(Error::Exit(code)),
@@ -41,68 +642,219 @@ impl Shell {
             }
 
             // 外部コマンドの実行を試す
-            let Some(name) = find_executable(&name)
+            // PATHが上書きされていればそれで探す
+            let path = match env.iter().rfind(|(name, _)| name == "PATH") {
+                Some((_, path)) => path.as_ref().map(OsString::from),
+                None => self.env_var("PATH"),
+            };
+            let path = self.commands.find(&name, path.as_deref(), &self.cwd);
+            let Some(path) = path
             else {
-                return Err(Error::CommandError(
-                    "コマンドが見つかりませんでした".to_string(),
-                ));
+                return Err(Error::CommandNotFound(builtin_name.into_owned()));
             };
-            let status =
-                match std::process::Command::new(name).args(args).status() {
-                    Ok(status) => status,
-                    Err(e) => return Err(Error::CommandError(e.to_string())),
+            let mut child = std::process::Command::new(path);
+            child.current_dir(&self.cwd);
+            self.pass_env(&mut child);
+            for (name, value) in env {
+                match value {
+                    Some(value) => child.env(name, value),
+                    None => child.env_remove(name),
                 };
+            }
+            let child = child
+                .args(args)
+                .stdin(streams.stdin.into_stdio())
+                .stdout(streams.stdout.into_stdio())
+                .stderr(streams.stderr.into_stdio())
+                .spawn()
+                .map_err(io_error)?;
+            children.push(child);
+            last = Stage::External;
         }
+        Ok(last)
+    }
+    /// 制御構文をパイプラインの段として実行する
+    ///
+    /// 途中の段は後の段と同時に実行する
+    fn block_stage<'scope>(
+        &mut self,
+        scope: &'scope Scope<'scope, '_>,
+        source: Source<'scope>,
+        stdin: &mut Stream,
+        is_last: bool,
+        f: impl FnOnce(&mut Self) -> Result<i32> + Send + 'scope,
+    ) -> Result<Stage> {
+        let streams = self.stage_streams(stdin, is_last, &[])?;
+        if is_last {
+            return Ok(Stage::Builtin(self.with_io(streams, f)?));
+        }
+        self.spawn_stage(scope, source, move |shell| {
+            shell.with_io(streams, f)
+        })?;
+        Ok(Stage::Builtin(0))
+    }
+    /// パイプラインの途中の段を別スレッドで実行する
+    ///
+    /// 出力が詰まらないよう後の段と同時に実行するので、サブシェルとして扱う
+    /// エラーはそのスレッドで表示する
+    fn spawn_stage<'scope>(
+        &self,
+        scope: &'scope Scope<'scope, '_>,
+        source: Source<'scope>,
+        f: impl FnOnce(&mut Self) -> Result<i32> + Send + 'scope,
+    ) -> Result<()> {
+        let subshell = self.clone();
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, move || match subshell.into_subshell(f) {
+                // 呼び出し元のシェルも中断する
+                Err(Error::Interrupted) => {
+                    INTERRUPTED.store(true, Ordering::SeqCst);
+                }
+                Err(e) if !e.is_control_flow() => {
+                    eprint!("{}", e.display(source));
+                }
+                _ => {}
+            })
+            .map_err(io_error)?;
         Ok(())
     }
-}
-
-/// 実行可能ファイルのフルパスを探索
-fn find_executable(name: &str) -> Option<PathBuf> {
-    let name = PathBuf::from(name);
-
-    // 探索する拡張子を取得
-    let extensions = name
-        .extension()
-        .map(|ext| vec![ext.to_os_string()])
-        .unwrap_or_else(get_pathext);
-    // 探索するパスを取得
-    let search_dirs = name
-        .parent()
-        .and_then(|parent| {
-            if parent.as_os_str().is_empty() {
-                None
+    /// パイプラインの段の標準入出力を用意してリダイレクトを適用する
+    ///
+    /// 途中の段の出力は次の段の入力に渡す
+    fn stage_streams(
+        &mut self,
+        stdin: &mut Stream,
+        is_last: bool,
+        redirects: &[Spanned<Redirect>],
+    ) -> Result<Streams> {
+        let (next_stdin, stdout) = if is_last {
+            (Stream::Stdin, self.io.stdout.try_clone().map_err(io_error)?)
+        }
+        else {
+            let (reader, writer) = std::io::pipe().map_err(io_error)?;
+            (Stream::PipeReader(reader), Stream::PipeWriter(writer))
+        };
+        let mut streams = Streams {
+            stdin: std::mem::replace(stdin, next_stdin),
+            stdout,
+            stderr: self.io.stderr.try_clone().map_err(io_error)?,
+        };
+        for redirect in redirects {
+            self.redirect(&mut streams, redirect)?;
+        }
+        Ok(streams)
+    }
+    /// 標準入出力を`streams`に替えて`f`を実行する
+    fn with_io<T>(
+        &mut self,
+        streams: Streams,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let io = std::mem::replace(&mut self.io, Arc::new(streams));
+        let result = f(self);
+        self.io = io;
+        result
+    }
+    /// リダイレクトを左から順に適用する
+    fn redirect(
+        &mut self,
+        streams: &mut Streams,
+        redirect: &Spanned<Redirect>,
+    ) -> Result<()> {
+        use std::fs::OpenOptions;
+        let open = |file: &Spanned<Word>, options: &mut OpenOptions| {
+            let [path] = <[OsString; 1]>::try_from(self.expand_word(file)?)
+                .map_err(|_| EvalError {
+                    kind: EvalErrorKind::AmbiguousRedirect,
+                    span: file.span(),
+                })?;
+            let path = PathBuf::from(path);
+            options
+                .open(self.cwd.join(&path))
+                .map(Stream::File)
+                .map_err(|e| {
+                    Error::CommandError(format!("{} : {e}", path.display()))
+                })
+        };
+        let Redirect { fd, target } = redirect.inner();
+        let stream = match target {
+            RedirectTarget::Read(file) => {
+                open(file, OpenOptions::new().read(true))?
             }
-            else {
-                Some(vec![parent.to_owned()])
+            RedirectTarget::Write(file) => open(
+                file,
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )?,
+            RedirectTarget::Append(file) => {
+                open(file, OpenOptions::new().append(true).create(true))?
             }
-        })
-        .unwrap_or_else(get_path);
-
-    let file_name = name.file_stem()?;
-    for dir in search_dirs {
-        for ext in &extensions {
-            let candidate = dir.join(file_name).with_extension(ext);
-            if candidate.exists() {
-                return Some(candidate);
+            RedirectTarget::Duplicate(source) => {
+                streams.get(*source.inner()).try_clone().map_err(io_error)?
             }
-        }
+        };
+        streams.set(*fd, stream);
+        Ok(())
     }
-    None
 }
-fn get_pathext() -> Vec<OsString> {
-    // var_osを使用するとより正確
-    std::env::var("PATHEXT")
-        .unwrap_or(".COM;.EXE;.BAT;.CMD".to_string())
-        .split(';')
-        .map(|s| OsString::from(s.trim_start_matches('.')))
-        .collect()
+
+/// パイプラインの最後のコマンドの種類
+enum Stage {
+    Builtin(i32),
+    External,
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::CommandError(e.to_string())
+}
+/// 値がパターンに一致すれば、束縛する変数とその値を返す
+fn match_pattern(
+    pattern: &Pattern,
+    value: &Value,
+) -> Option<Vec<(String, Value)>> {
+    let matched = match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Bind(name), value) => {
+            return Some(vec![(name.clone(), value.clone())]);
+        }
+        (Pattern::String(pattern), Value::String(string)) => pattern == string,
+        (Pattern::String(pattern), Value::Path(path)) => {
+            path.as_os_str() == pattern.as_str()
+        }
+        (Pattern::Glob(pattern), Value::String(string)) => {
+            expand::glob_matches(pattern, string)
+        }
+        (Pattern::Glob(pattern), Value::Path(path)) => {
+            expand::glob_matches(pattern, &path.to_string_lossy())
+        }
+        (Pattern::Int(pattern), Value::Int(int)) => pattern == int,
+        (Pattern::Bool(pattern), Value::Bool(bool)) => pattern == bool,
+        (Pattern::None, Value::Option(None)) => true,
+        (Pattern::Some(pattern), Value::Option(Some(value))) => {
+            return match_pattern(pattern.inner(), value);
+        }
+        _ => false,
+    };
+    matched.then(Vec::new)
+}
+/// すぐに終わる入力
+fn empty_stream() -> Result<Stream> {
+    let (reader, _) = std::io::pipe().map_err(io_error)?;
+    Ok(Stream::PipeReader(reader))
 }
-fn get_path() -> Vec<PathBuf> {
-    // var_osを使用するとより正確
-    std::env::var("PATH")
-        .unwrap_or_default()
-        .split(';')
-        .map(PathBuf::from)
-        .collect()
+/// 終了ステータスを数値にする
+///
+/// シグナルで終了した場合は128+シグナル番号
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
 }
+
+#[cfg(test)]
+mod test;
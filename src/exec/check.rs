@@ -0,0 +1,786 @@
+use super::{EvalError, EvalErrorKind, Shell, expand::has_glob, var};
+use crate::{
+    parse::{
+        Assign, AssignTarget, AssignValue, BinaryOp, Block, Command, Condition,
+        Expr, ForIter, Function, Group, If, Loop, LoopKind, Match, Pattern,
+        Pipe, Postfix, Redirect, RedirectTarget, ShellCommand, Span, Spanned,
+        SpecialVar, Statement, UnaryOp, Word,
+    },
+    value::Type,
+};
+use std::collections::{HashMap, hash_map::Entry};
+
+type Result<T> = ::std::result::Result<T, EvalError>;
+
+impl Shell {
+    /// 実行前に型を検査する
+    ///
+    /// 現在のシェル変数の型から始めて、代入による型の変化を追跡する
+    pub fn check(&self, cmd: &ShellCommand) -> Result<()> {
+        self.checker().check(cmd)
+    }
+    /// 現在のシェル変数の型を持つ型検査器
+    ///
+    /// 実行せずに複数の行を続けて検査するときに使う
+    pub fn checker(&self) -> Checker {
+        let vars = self
+            .vars
+            .iter()
+            .map(|(name, var)| (name.clone(), var.ty.clone()))
+            .collect();
+        Checker {
+            vars,
+            labels: Vec::new(),
+            in_function: false,
+        }
+    }
+}
+
+/// 型検査器
+#[derive(Clone, Debug, Default)]
+pub struct Checker {
+    /// シェル変数の型
+    vars: HashMap<String, Type>,
+    /// 外側から順に、囲んでいるループのラベル
+    labels: Vec<Option<String>>,
+    /// 関数の本体を検査しているか
+    in_function: bool,
+}
+impl Checker {
+    pub fn check(&mut self, cmd: &ShellCommand) -> Result<()> {
+        self.check_list(&cmd.commands)
+    }
+    fn check_list(
+        &mut self,
+        commands: &[(Statement, Option<Pipe>)],
+    ) -> Result<()> {
+        for (statement, pipe) in commands {
+            self.check_stage(statement, matches!(pipe, Some(Pipe::Pipe)))?;
+        }
+        Ok(())
+    }
+    /// パイプラインの段を検査する
+    ///
+    /// 実行時と同じく、後ろにパイプが続くグループはサブシェルとして扱う
+    fn check_stage(
+        &mut self,
+        statement: &Statement,
+        piped: bool,
+    ) -> Result<()> {
+        match statement {
+            Statement::Group(group) if piped => self.check_subshell(group),
+            Statement::If(_) | Statement::Loop(_) | Statement::Match(_)
+                if piped =>
+            {
+                self.subshell().check_statement(statement)
+            }
+            statement => self.check_statement(statement),
+        }
+    }
+    fn check_statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Command(command) => self.check_command(command),
+            Statement::Assign(assign) => self.check_assign(assign),
+            Statement::If(if_) => self.check_if(if_),
+            Statement::Loop(loop_) => self.check_loop(loop_),
+            Statement::Match(match_) => self.check_match(match_),
+            Statement::Group(group) => self.check_group(group),
+            Statement::Break(label) => self.check_loop_label(label, "break"),
+            Statement::Continue(label) => {
+                self.check_loop_label(label, "continue")
+            }
+            Statement::Function(function) => self.check_function(function),
+            Statement::Return(value) => {
+                if !self.in_function {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::ReturnOutsideFunction,
+                        span: value.span(),
+                    });
+                }
+                if let Some(word) = value.inner() {
+                    self.check_word(word)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    /// 引数の変数だけがある状態で関数の本体を検査する
+    ///
+    /// 本体での代入は呼び出し元の変数に影響しない
+    fn check_function(&self, function: &Function) -> Result<()> {
+        // 実行時と同じく、関数の中からは引数の変数だけが見える
+        let mut body = Checker {
+            in_function: true,
+            ..Checker::default()
+        };
+        for param in &function.params {
+            let ty = param.ty.inner();
+            if let Some(default) = &param.default {
+                let found = match default.inner() {
+                    Expr::Array(elements)
+                        if elements.is_empty()
+                            && matches!(ty, Type::Array(_)) =>
+                    {
+                        ty.clone()
+                    }
+                    _ => body.check_expr(default)?,
+                };
+                if !var::can_assign(ty, &found) {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::VarTypeMismatch {
+                            name: format!("%{}", param.name.inner()),
+                            expected: ty.clone(),
+                            found,
+                        },
+                        span: default.span(),
+                    });
+                }
+            }
+            body.vars.insert(param.name.inner().clone(), ty.clone());
+        }
+        body.check_list(&function.block.commands)
+    }
+    /// 分岐ごとに検査して、代入された変数の型をまとめる
+    fn check_if(&mut self, if_: &If) -> Result<()> {
+        let mut branches = Vec::new();
+        for (condition, block) in &if_.branches {
+            self.check_condition(condition)?;
+            branches.push(self.check_branch(block)?);
+        }
+        match &if_.else_block {
+            Some(block) => branches.push(self.check_branch(block)?),
+            // どのブロックも実行されない場合
+            None => branches.push(self.vars.clone()),
+        }
+        self.vars = merge_vars(branches);
+        Ok(())
+    }
+    fn check_condition(&mut self, condition: &Condition) -> Result<()> {
+        match condition {
+            Condition::Expr(word) => {
+                let ty = self.check_word(word)?;
+                if !Type::Bool.accepts(&ty) {
+                    return Err(type_mismatch(word.span(), "bool", ty));
+                }
+            }
+            Condition::Command(pipeline) => {
+                for (i, statement) in pipeline.iter().enumerate() {
+                    self.check_stage(statement, i + 1 < pipeline.len())?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// ブロックが実行されない場合とまとめて変数の型を更新する
+    fn check_loop(&mut self, loop_: &Loop) -> Result<()> {
+        let var = match &loop_.kind {
+            LoopKind::While(condition) => {
+                self.check_condition(condition)?;
+                None
+            }
+            LoopKind::For { var, iter } => {
+                Some((var.inner(), self.check_for_iter(iter)?))
+            }
+        };
+        let mut body = self.clone();
+        if let Some((name, ty)) = &var {
+            body.vars.insert(name.to_string(), ty.clone());
+        }
+        body.labels.push(loop_.label.clone());
+        body.check_list(&loop_.block.commands)?;
+        let mut vars = body.vars;
+        // ループ変数はループの後で元に戻る
+        if let Some((name, _)) = var {
+            match self.vars.get(name) {
+                Some(ty) => vars.insert(name.clone(), ty.clone()),
+                None => vars.remove(name),
+            };
+        }
+        self.vars = merge_vars(vec![self.vars.clone(), vars]);
+        Ok(())
+    }
+    /// サブシェルでは変数の変更を外に出さず、ループや関数の外として検査する
+    fn check_group(&mut self, group: &Group) -> Result<()> {
+        if group.subshell {
+            return self.check_subshell(group);
+        }
+        self.check_list(&group.block.commands)?;
+        self.check_redirects(&group.redirects)
+    }
+    /// 変数の変更が外に残らず、外のループや関数を抜けられないものとして検査する
+    fn check_subshell(&self, group: &Group) -> Result<()> {
+        self.subshell().check_list(&group.block.commands)?;
+        self.check_redirects(&group.redirects)
+    }
+    /// サブシェルを検査するための複製
+    fn subshell(&self) -> Self {
+        let mut subshell = self.clone();
+        subshell.labels.clear();
+        subshell.in_function = false;
+        subshell
+    }
+    /// 腕ごとに検査して、代入された変数の型をまとめる
+    ///
+    /// bool型とoption型の値はすべての値に一致する腕が必要
+    fn check_match(&mut self, match_: &Match) -> Result<()> {
+        let ty = self.check_word(&match_.value)?;
+        let mut branches = Vec::new();
+        for arm in &match_.arms {
+            let mut binding = None;
+            for (i, pattern) in arm.patterns.iter().enumerate() {
+                let bound = check_pattern(pattern, &ty)?;
+                let name = |bound: &Option<(String, Type)>| {
+                    bound.as_ref().map(|(name, _)| name.clone())
+                };
+                if i == 0 {
+                    binding = bound;
+                }
+                // |で並べたパターンは同じ変数を束縛する
+                else if name(&bound) != name(&binding) {
+                    let name = name(&bound).or(name(&binding)).unwrap();
+                    return Err(EvalError {
+                        kind: EvalErrorKind::InconsistentBinding(name),
+                        span: pattern.span(),
+                    });
+                }
+            }
+            let mut branch = self.clone();
+            branch.vars.extend(binding.clone());
+            branch.check_list(&arm.block.commands)?;
+            // 束縛した変数は腕の後で元に戻る
+            if let Some((name, _)) = binding {
+                match self.vars.get(&name) {
+                    Some(ty) => branch.vars.insert(name, ty.clone()),
+                    None => branch.vars.remove(&name),
+                };
+            }
+            branches.push(branch.vars);
+        }
+        let patterns: Vec<_> = match_
+            .arms
+            .iter()
+            .flat_map(|arm| arm.patterns.iter().map(Spanned::inner))
+            .collect();
+        match missing_pattern(&patterns, &ty) {
+            Some(missing) if matches!(ty, Type::Bool | Type::Option(_)) => {
+                return Err(EvalError {
+                    kind: EvalErrorKind::NonExhaustive(missing),
+                    span: match_.value.span(),
+                });
+            }
+            // どの腕も実行されない場合
+            Some(_) => branches.push(self.vars.clone()),
+            None => {}
+        }
+        self.vars = merge_vars(branches);
+        Ok(())
+    }
+    /// `for`で繰り返す値の要素の型
+    fn check_for_iter(&self, iter: &ForIter) -> Result<Type> {
+        match iter {
+            ForIter::Word(word) => match self.check_word(word)? {
+                Type::Array(ty) => Ok(*ty),
+                Type::Unknown => Ok(Type::Unknown),
+                ty => Err(type_mismatch(word.span(), "array", ty)),
+            },
+            ForIter::Range { start, end, .. } => {
+                self.check_int(start)?;
+                self.check_int(end)?;
+                Ok(Type::Int)
+            }
+        }
+    }
+    /// `break`や`continue`が抜けるループがあるか
+    fn check_loop_label(
+        &self,
+        label: &Spanned<Option<String>>,
+        keyword: &'static str,
+    ) -> Result<()> {
+        let kind = match label.inner() {
+            _ if self.labels.is_empty() => EvalErrorKind::OutsideLoop(keyword),
+            Some(name) if !self.labels.contains(&Some(name.clone())) => {
+                EvalErrorKind::UndefinedLabel(name.clone())
+            }
+            _ => return Ok(()),
+        };
+        Err(EvalError {
+            kind,
+            span: label.span(),
+        })
+    }
+    /// ブロックを検査して、実行後の変数の型を返す
+    fn check_branch(&self, block: &Block) -> Result<HashMap<String, Type>> {
+        let mut branch = self.clone();
+        branch.check_list(&block.commands)?;
+        Ok(branch.vars)
+    }
+    fn check_command(&self, command: &Command) -> Result<()> {
+        for env in &command.env {
+            if let Some(value) = &env.value {
+                let ty = self.check_word(value)?;
+                check_env(&env.name, ty, value.span())?;
+            }
+        }
+        self.check_word(&command.name)?;
+        for arg in &command.args {
+            self.check_word(arg)?;
+        }
+        self.check_redirects(&command.redirects)
+    }
+    fn check_redirects(&self, redirects: &[Spanned<Redirect>]) -> Result<()> {
+        for redirect in redirects {
+            match &redirect.inner().target {
+                RedirectTarget::Read(file)
+                | RedirectTarget::Write(file)
+                | RedirectTarget::Append(file) => {
+                    self.check_word(file)?;
+                }
+                RedirectTarget::Duplicate(_) => {}
+            }
+        }
+        Ok(())
+    }
+    /// 実行時の代入と同じ規則で変数の型を更新する
+    fn check_assign(&mut self, assign: &Assign) -> Result<()> {
+        let name = match assign.target.inner() {
+            AssignTarget::EnvVar(name) => {
+                if let AssignValue::Word(word) = &assign.value {
+                    check_env(name, self.check_word(word)?, word.span())?;
+                }
+                return Ok(());
+            }
+            AssignTarget::ShellVar(name) => name,
+            AssignTarget::Element(name, index) => {
+                return self.check_element(&assign.target, name, index, assign);
+            }
+        };
+        let declared = match &assign.ty {
+            Some(ty) => Some(ty.inner().clone()),
+            None => self.vars.get(name).cloned(),
+        };
+        let (found, span) = match &assign.value {
+            AssignValue::Word(word) => (
+                Some(self.check_value(word, declared.as_ref())?),
+                word.span(),
+            ),
+            AssignValue::None(none) => (None, none.span()),
+        };
+        match (declared, found) {
+            (None, None) => {
+                return Err(EvalError {
+                    kind: EvalErrorKind::UntypedNone,
+                    span,
+                });
+            }
+            (None, Some(found)) => {
+                self.vars.insert(name.clone(), found);
+            }
+            (Some(ty @ Type::Option(_)), None) => {
+                self.vars.insert(name.clone(), ty);
+            }
+            (Some(_), None) => {
+                self.vars.remove(name);
+            }
+            (Some(ty), Some(found)) => {
+                if !var::can_assign(&ty, &found) {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::VarTypeMismatch {
+                            name: format!("%{name}"),
+                            expected: ty,
+                            found,
+                        },
+                        span,
+                    });
+                }
+                self.vars.insert(name.clone(), ty);
+            }
+        }
+        Ok(())
+    }
+    /// 配列の要素への代入
+    fn check_element(
+        &self,
+        target: &Spanned<AssignTarget>,
+        name: &str,
+        index: &Spanned<Expr>,
+        assign: &Assign,
+    ) -> Result<()> {
+        self.check_int(index)?;
+        let ty = match self.vars.get(name) {
+            Some(Type::Array(ty)) => (**ty).clone(),
+            Some(Type::Unknown) => Type::Unknown,
+            Some(ty) => {
+                return Err(type_mismatch(target.span(), "array", ty.clone()));
+            }
+            None => {
+                return Err(EvalError {
+                    kind: EvalErrorKind::UndefinedVariable(name.to_string()),
+                    span: target.span(),
+                });
+            }
+        };
+        let (found, span) = match &assign.value {
+            AssignValue::Word(word) => {
+                (self.check_value(word, Some(&ty))?, word.span())
+            }
+            AssignValue::None(none) => {
+                (Type::Option(Box::new(Type::Unknown)), none.span())
+            }
+        };
+        if !var::can_assign(&ty, &found) {
+            return Err(EvalError {
+                kind: EvalErrorKind::VarTypeMismatch {
+                    name: target.to_string(),
+                    expected: ty,
+                    found,
+                },
+                span,
+            });
+        }
+        Ok(())
+    }
+    /// 代入する値の型
+    ///
+    /// 代入先の型`expected`が分かっていれば空の配列を代入できる
+    fn check_value(
+        &self,
+        word: &Spanned<Word>,
+        expected: Option<&Type>,
+    ) -> Result<Type> {
+        if let (Word::Expr(expr), Some(expected @ Type::Array(_))) =
+            (word.inner(), expected)
+            && let Expr::Array(elements) = expr.inner()
+            && elements.is_empty()
+        {
+            return Ok(expected.clone());
+        }
+        self.check_word(word)
+    }
+    /// 配列のインデックスや範囲などのint型の式
+    fn check_int(&self, index: &Spanned<Expr>) -> Result<()> {
+        let ty = self.check_expr(index)?;
+        if !Type::Int.accepts(&ty) {
+            return Err(type_mismatch(index.span(), "int", ty));
+        }
+        Ok(())
+    }
+    fn check_word(&self, word: &Spanned<Word>) -> Result<Type> {
+        self.check_word_at(word.inner(), word.span())
+    }
+    fn check_word_at(&self, word: &Word, span: Span) -> Result<Type> {
+        match word {
+            Word::Literal(_) => Ok(Type::String),
+            Word::PathLiteral(pattern) => Ok(if has_glob(pattern) {
+                Type::Array(Box::new(Type::Path))
+            }
+            else {
+                Type::Path
+            }),
+            Word::SpecialVar(var) => Ok(match var {
+                SpecialVar::ExitStatus | SpecialVar::Pid => Type::Int,
+                SpecialVar::BackgroundPid => Type::Option(Box::new(Type::Int)),
+                SpecialVar::ShellName => Type::String,
+            }),
+            Word::EnvVar(_) => Ok(Type::Option(Box::new(Type::String))),
+            Word::ShellVar(name) => {
+                self.vars.get(name).cloned().ok_or_else(|| EvalError {
+                    kind: EvalErrorKind::UndefinedVariable(name.clone()),
+                    span,
+                })
+            }
+            Word::Postfix(operand, op) => {
+                self.postfix_type(self.check_word(operand)?, op, operand.span())
+            }
+            Word::UnwrapOr(operand, default) => unwrap_or_type(
+                self.check_word(operand)?,
+                operand.span(),
+                self.check_word(default)?,
+                default.span(),
+            ),
+            Word::Expr(expr) => self.check_expr(expr),
+        }
+    }
+    fn check_expr(&self, expr: &Spanned<Expr>) -> Result<Type> {
+        match expr.inner() {
+            Expr::Word(word) => self.check_word_at(word, expr.span()),
+            Expr::Int(_) => Ok(Type::Int),
+            Expr::Float(_) => Ok(Type::Float),
+            Expr::Bool(_) => Ok(Type::Bool),
+            Expr::Unary(op, operand) => match (op, self.check_expr(operand)?) {
+                (_, Type::Unknown) => Ok(Type::Unknown),
+                (UnaryOp::Neg, ty @ (Type::Int | Type::Float))
+                | (UnaryOp::Not, ty @ Type::Bool) => Ok(ty),
+                (op, operand) => Err(EvalError {
+                    kind: EvalErrorKind::InvalidUnaryOperand {
+                        op: *op,
+                        operand,
+                    },
+                    span: expr.span(),
+                }),
+            },
+            // 論理演算は左右それぞれがbool型
+            Expr::Binary(left, BinaryOp::And | BinaryOp::Or, right) => {
+                for operand in [left, right] {
+                    let ty = self.check_expr(operand)?;
+                    if !Type::Bool.accepts(&ty) {
+                        return Err(type_mismatch(operand.span(), "bool", ty));
+                    }
+                }
+                Ok(Type::Bool)
+            }
+            Expr::Binary(left, op, right) => {
+                let left = self.check_expr(left)?;
+                let right = self.check_expr(right)?;
+                binary_type(*op, &left, &right).ok_or_else(|| EvalError {
+                    kind: EvalErrorKind::InvalidBinaryOperands {
+                        op: *op,
+                        left,
+                        right,
+                    },
+                    span: expr.span(),
+                })
+            }
+            Expr::Postfix(operand, op) => {
+                self.postfix_type(self.check_expr(operand)?, op, operand.span())
+            }
+            Expr::UnwrapOr(operand, default) => unwrap_or_type(
+                self.check_expr(operand)?,
+                operand.span(),
+                self.check_expr(default)?,
+                default.span(),
+            ),
+            Expr::As(operand, to) => {
+                let from = self.check_expr(operand)?;
+                if !from.can_cast(to) {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::InvalidCast {
+                            from,
+                            to: to.clone(),
+                        },
+                        span: expr.span(),
+                    });
+                }
+                Ok(to.clone())
+            }
+            // 要素の型はすべて同じ
+            Expr::Array(elements) => {
+                let Some((first, rest)) = elements.split_first()
+                else {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::UntypedEmptyArray,
+                        span: expr.span(),
+                    });
+                };
+                let mut ty = self.check_expr(first)?;
+                for element in rest {
+                    let found = self.check_expr(element)?;
+                    if !ty.accepts(&found) {
+                        return Err(type_mismatch(
+                            element.span(),
+                            ty.name(),
+                            found,
+                        ));
+                    }
+                    if ty == Type::Unknown {
+                        ty = found;
+                    }
+                }
+                Ok(Type::Array(Box::new(ty)))
+            }
+            Expr::Some(value) => {
+                Ok(Type::Option(Box::new(self.check_expr(value)?)))
+            }
+            Expr::None => Ok(Type::Option(Box::new(Type::Unknown))),
+        }
+    }
+    fn postfix_type(&self, ty: Type, op: &Postfix, span: Span) -> Result<Type> {
+        match op {
+            Postfix::IsSome => option_type(ty, span).map(|_| Type::Bool),
+            Postfix::Unwrap => option_type(ty, span),
+            Postfix::Len => match ty {
+                Type::String | Type::Array(_) | Type::Path | Type::Unknown => {
+                    Ok(Type::Int)
+                }
+                ty => Err(EvalError {
+                    kind: EvalErrorKind::NoLength(ty),
+                    span,
+                }),
+            },
+            Postfix::Index(index) => {
+                self.check_int(index)?;
+                match ty {
+                    Type::Array(ty) => Ok(Type::Option(ty)),
+                    Type::Unknown => Ok(Type::Option(Box::new(Type::Unknown))),
+                    ty => Err(type_mismatch(span, "array", ty)),
+                }
+            }
+        }
+    }
+}
+
+fn type_mismatch(span: Span, expected: &'static str, found: Type) -> EvalError {
+    EvalError {
+        kind: EvalErrorKind::TypeMismatch { expected, found },
+        span,
+    }
+}
+/// パターンが型`ty`の値に使えるか検査して、束縛する変数とその型を返す
+fn check_pattern(
+    pattern: &Spanned<Pattern>,
+    ty: &Type,
+) -> Result<Option<(String, Type)>> {
+    let expected = match (pattern.inner(), ty) {
+        (Pattern::Bind(name), _) => {
+            return Ok(Some((name.clone(), ty.clone())));
+        }
+        (Pattern::Wildcard, _) | (_, Type::Unknown) => None,
+        (Pattern::String(_) | Pattern::Glob(_), Type::String | Type::Path)
+        | (Pattern::Int(_), Type::Int)
+        | (Pattern::Bool(_), Type::Bool)
+        | (Pattern::None, Type::Option(_)) => None,
+        (Pattern::Some(pattern), Type::Option(inner)) => {
+            return check_pattern(pattern, inner);
+        }
+        (Pattern::String(_) | Pattern::Glob(_), _) => Some("string"),
+        (Pattern::Int(_), _) => Some("int"),
+        (Pattern::Bool(_), _) => Some("bool"),
+        (Pattern::None | Pattern::Some(_), _) => Some("option"),
+    };
+    match expected {
+        Some(expected) => {
+            Err(type_mismatch(pattern.span(), expected, ty.clone()))
+        }
+        None => Ok(None),
+    }
+}
+/// パターンのどれにも一致しない型`ty`の値があれば、その値を表すパターンを返す
+fn missing_pattern(patterns: &[&Pattern], ty: &Type) -> Option<String> {
+    if patterns
+        .iter()
+        .any(|pattern| matches!(pattern, Pattern::Wildcard | Pattern::Bind(_)))
+    {
+        return None;
+    }
+    match ty {
+        Type::Bool => [true, false]
+            .into_iter()
+            .find(|bool| !patterns.contains(&&Pattern::Bool(*bool)))
+            .map(|bool| bool.to_string()),
+        Type::Option(inner) => {
+            if !patterns.contains(&&Pattern::None) {
+                return Some("none".to_string());
+            }
+            let some: Vec<_> = patterns
+                .iter()
+                .filter_map(|pattern| match pattern {
+                    Pattern::Some(pattern) => Some(pattern.inner()),
+                    _ => None,
+                })
+                .collect();
+            if some.is_empty() {
+                return Some("some(_)".to_string());
+            }
+            missing_pattern(&some, inner)
+                .map(|missing| format!("some({missing})"))
+        }
+        Type::Unknown => None,
+        // 文字列や数値はすべてを並べられない
+        _ => Some("_".to_string()),
+    }
+}
+/// 分岐ごとの変数の型をまとめる
+///
+/// 分岐によって型が異なる変数はunknown型にする
+fn merge_vars(branches: Vec<HashMap<String, Type>>) -> HashMap<String, Type> {
+    let mut merged = HashMap::new();
+    for vars in branches {
+        for (name, ty) in vars {
+            match merged.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(ty);
+                }
+                Entry::Occupied(mut entry) => {
+                    if *entry.get() != ty {
+                        entry.insert(Type::Unknown);
+                    }
+                }
+            }
+        }
+    }
+    merged
+}
+/// 環境変数に設定できるのは文字列のみ
+fn check_env(name: &str, found: Type, span: Span) -> Result<()> {
+    let string = Type::Option(Box::new(Type::String));
+    if string.accepts(&found) || Type::String.accepts(&found) {
+        Ok(())
+    }
+    else {
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: format!("${name}"),
+                expected: Type::String,
+                found,
+            },
+            span,
+        })
+    }
+}
+/// option型の要素の型
+fn option_type(ty: Type, span: Span) -> Result<Type> {
+    match ty {
+        Type::Option(inner) => Ok(*inner),
+        Type::Unknown => Ok(Type::Unknown),
+        ty => Err(type_mismatch(span, "option", ty)),
+    }
+}
+/// `^`のデフォルト値は要素と同じ型
+fn unwrap_or_type(
+    ty: Type,
+    span: Span,
+    default: Type,
+    default_span: Span,
+) -> Result<Type> {
+    let inner = option_type(ty, span)?;
+    if !inner.accepts(&default) {
+        return Err(type_mismatch(default_span, inner.name(), default));
+    }
+    Ok(if inner == Type::Unknown {
+        default
+    }
+    else {
+        inner
+    })
+}
+/// 二項演算の結果の型
+///
+/// 整数と小数を混ぜた演算のような暗黙の型変換は許可しない
+fn binary_type(op: BinaryOp, left: &Type, right: &Type) -> Option<Type> {
+    use BinaryOp::*;
+    match (op, left, right) {
+        (Eq | Ne | Lt | Le | Gt | Ge, Type::Unknown, _)
+        | (Eq | Ne | Lt | Le | Gt | Ge, _, Type::Unknown) => Some(Type::Bool),
+        (_, Type::Unknown, _) | (_, _, Type::Unknown) => Some(Type::Unknown),
+        (Add | Sub | Mul | Div | Rem, Type::Int, Type::Int) => Some(Type::Int),
+        (Add | Sub | Mul | Div | Rem, Type::Float, Type::Float) => {
+            Some(Type::Float)
+        }
+        (Add, Type::String, Type::String) => Some(Type::String),
+        // 配列の末尾に追加する
+        (Add, Type::Array(ty), element) if ty.accepts(element) => {
+            Some(Type::Array(Box::new(if **ty == Type::Unknown {
+                element.clone()
+            }
+            else {
+                (**ty).clone()
+            })))
+        }
+        (Eq | Ne, left, right) if left.accepts(right) => Some(Type::Bool),
+        (Lt | Le | Gt | Ge, Type::Int | Type::Float | Type::String, right)
+            if left == right =>
+        {
+            Some(Type::Bool)
+        }
+        _ => None,
+    }
+}
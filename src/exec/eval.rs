@@ -0,0 +1,525 @@
+use super::{
+    Shell,
+    expand::{expand_path, has_glob},
+};
+use crate::{
+    parse::{
+        BinaryOp, Expr, Postfix, Span, Spanned, SpecialVar, UnaryOp, Word,
+        error::Source,
+    },
+    value::{Type, Value},
+};
+use std::{ffi::OsString, fmt::Display};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    UndefinedVariable(String),
+    NotUnicodeEnvVar(String),
+    NoGlobMatch(String),
+    UnknownUser(String),
+    HomeDirNotFound,
+    TypeMismatch {
+        expected: &'static str,
+        found: Type,
+    },
+    UnwrapNone(String),
+    EmptyCommand,
+    AmbiguousRedirect,
+    InvalidUnaryOperand {
+        op: UnaryOp,
+        operand: Type,
+    },
+    InvalidBinaryOperands {
+        op: BinaryOp,
+        left: Type,
+        right: Type,
+    },
+    DivisionByZero,
+    Overflow,
+    IndexOutOfRange {
+        index: i64,
+        len: usize,
+    },
+    UntypedEmptyArray,
+    /// 型のない変数への`none`の代入
+    UntypedNone,
+    /// `@`で長さを取得できない型
+    NoLength(Type),
+    InvalidCast {
+        from: Type,
+        to: Type,
+    },
+    CastFailed {
+        value: String,
+        to: Type,
+    },
+    VarTypeMismatch {
+        name: String,
+        expected: Type,
+        found: Type,
+    },
+    /// ループの外の`break`や`continue`
+    OutsideLoop(&'static str),
+    UndefinedLabel(String),
+    /// 関数の外の`return`
+    ReturnOutsideFunction,
+    /// デフォルト値のない引数が渡されていない
+    MissingArgument(String),
+    TooManyArguments(String),
+    /// 関数の呼び出しが深すぎる
+    RecursionLimit(String),
+    /// `match`のパターンが一致しない値
+    NonExhaustive(String),
+    /// `|`で並べたパターンの一部にしかない変数
+    InconsistentBinding(String),
+}
+impl Display for EvalErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use EvalErrorKind::*;
+        match self {
+            UndefinedVariable(name) => write!(f, "未定義の変数 '{name}' です"),
+            NotUnicodeEnvVar(name) => {
+                write!(f, "環境変数 '{name}' の値がUTF-8ではありません")
+            }
+            NoGlobMatch(pattern) => {
+                write!(f, "'{pattern}' に一致するパスがありません")
+            }
+            UnknownUser(user) => {
+                write!(f, "ユーザー '{user}' が見つかりません")
+            }
+            HomeDirNotFound => {
+                write!(f, "ホームディレクトリの取得に失敗しました")
+            }
+            TypeMismatch { expected, found } => {
+                write!(f, "{expected}型が必要ですが{found}型です")
+            }
+            UnwrapNone(word) => write!(f, "{word} の値がありません"),
+            EmptyCommand => write!(f, "コマンド名が空です"),
+            AmbiguousRedirect => write!(f, "リダイレクト先は1つにしてください"),
+            InvalidUnaryOperand { op, operand } => {
+                write!(f, "{operand}型に{op}は使えません")
+            }
+            InvalidBinaryOperands { op, left, right } => {
+                write!(f, "{left}型と{right}型に{op}は使えません")
+            }
+            DivisionByZero => write!(f, "0で割ることはできません"),
+            Overflow => write!(f, "整数がオーバーフローしました"),
+            IndexOutOfRange { index, len } => {
+                write!(f, "インデックス{index}は要素数{len}の範囲外です")
+            }
+            UntypedEmptyArray => {
+                write!(f, "空の配列には型の指定が必要です")
+            }
+            UntypedNone => write!(f, "noneの代入には型の指定が必要です"),
+            NoLength(ty) => write!(f, "{ty}型の長さは取得できません"),
+            InvalidCast { from, to } => {
+                write!(f, "{from}型は{to}型に変換できません")
+            }
+            CastFailed { value, to } => {
+                write!(f, "'{value}' は{to}型に変換できません")
+            }
+            VarTypeMismatch {
+                name,
+                expected,
+                found,
+            } => {
+                write!(f, "{name} は{expected}型ですが{found}型の値です")
+            }
+            OutsideLoop(keyword) => {
+                write!(f, "{keyword}はループの中でしか使えません")
+            }
+            UndefinedLabel(label) => {
+                write!(f, "ラベル '{label} のループがありません")
+            }
+            ReturnOutsideFunction => {
+                write!(f, "returnは関数の中でしか使えません")
+            }
+            MissingArgument(name) => write!(f, "引数 {name} が必要です"),
+            TooManyArguments(function) => {
+                write!(f, "関数 '{function}' の引数が多すぎます")
+            }
+            RecursionLimit(function) => write!(
+                f,
+                "関数 '{function}' の呼び出しが深すぎます（上限は{}）",
+                super::function::MAX_CALL_DEPTH
+            ),
+            NonExhaustive(missing) => {
+                write!(f, "パターン {missing} に一致する腕がありません")
+            }
+            InconsistentBinding(name) => {
+                write!(f, "%{name} を|のすべてのパターンで束縛してください")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub span: Span,
+}
+impl EvalError {
+    pub fn display<'a>(&self, source: impl Into<Source<'a>>) -> String {
+        crate::parse::error::display_span(source, self.span.clone(), &self.kind)
+    }
+}
+type Result<T> = ::std::result::Result<T, EvalError>;
+
+pub(super) fn type_mismatch(
+    span: Span,
+    expected: &'static str,
+    found: &Value,
+) -> EvalError {
+    EvalError {
+        kind: EvalErrorKind::TypeMismatch {
+            expected,
+            found: found.ty(),
+        },
+        span,
+    }
+}
+/// option型の値を取り出す
+fn into_option(value: Value, span: Span) -> Result<Option<Value>> {
+    match value {
+        Value::Option(value) => Ok(value.map(|value| *value)),
+        value => Err(type_mismatch(span, "option", &value)),
+    }
+}
+/// 負のインデックスは末尾から数える
+pub(super) fn element_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        len.checked_sub(index.unsigned_abs() as usize)?
+    }
+    else {
+        index as usize
+    };
+    (index < len).then_some(index)
+}
+/// `as`による型変換
+///
+/// 小数から整数への変換は0の方向に切り捨てる
+pub(super) fn cast(
+    value: Value,
+    to: &Type,
+) -> ::std::result::Result<Value, EvalErrorKind> {
+    use Value::*;
+    let failed = |value: &dyn Display| EvalErrorKind::CastFailed {
+        value: value.to_string(),
+        to: to.clone(),
+    };
+    let from = value.ty();
+    if !from.can_cast(to) {
+        return Err(EvalErrorKind::InvalidCast {
+            from,
+            to: to.clone(),
+        });
+    }
+    match (value, to) {
+        (value, to) if to.accepts(&from) => Ok(value),
+        (Int(int), Type::Float) => Ok(Float(int as f64)),
+        (Float(float), Type::Int) => {
+            // i64の範囲外とNaNは変換できない
+            let truncated = float.trunc();
+            if (-9223372036854775808.0..9223372036854775808.0)
+                .contains(&truncated)
+            {
+                Ok(Int(truncated as i64))
+            }
+            else {
+                Err(failed(&float))
+            }
+        }
+        (Bool(bool), Type::Int) => Ok(Int(bool.into())),
+        (Int(int), Type::String) => Ok(String(int.to_string())),
+        (Float(float), Type::String) => Ok(String(format!("{float:?}"))),
+        (Bool(bool), Type::String) => Ok(String(bool.to_string())),
+        (String(string), Type::Int) => {
+            string.parse().map(Int).map_err(|_| failed(&string))
+        }
+        (String(string), Type::Float) => {
+            string.parse().map(Float).map_err(|_| failed(&string))
+        }
+        (String(string), Type::Bool) => {
+            string.parse().map(Bool).map_err(|_| failed(&string))
+        }
+        (String(string), Type::Path) => Ok(Path(string.into())),
+        (Path(path), Type::String) => match path.into_os_string().into_string()
+        {
+            Ok(string) => Ok(String(string)),
+            Err(path) => Err(failed(&path.to_string_lossy())),
+        },
+        _ => unreachable!("can_castで確認済み"),
+    }
+}
+/// 比較演算の結果
+///
+/// NaNとの比較は常にfalseになる
+fn compare(op: BinaryOp, ordering: Option<std::cmp::Ordering>) -> Value {
+    use std::cmp::Ordering::*;
+    Value::Bool(match op {
+        BinaryOp::Lt => ordering == Some(Less),
+        BinaryOp::Le => matches!(ordering, Some(Less | Equal)),
+        BinaryOp::Gt => ordering == Some(Greater),
+        _ => matches!(ordering, Some(Greater | Equal)),
+    })
+}
+
+impl Shell {
+    /// 単語を評価してコマンドの引数に展開する
+    pub(super) fn expand_word(
+        &self,
+        word: &Spanned<Word>,
+    ) -> Result<Vec<OsString>> {
+        let mut args = Vec::new();
+        self.eval_word(word)?.into_args(&mut args);
+        Ok(args)
+    }
+    /// 単語を評価する
+    pub(super) fn eval_word(&self, word: &Spanned<Word>) -> Result<Value> {
+        self.eval_word_at(word.inner(), word.span())
+    }
+    /// `span`の位置にある単語を評価する
+    fn eval_word_at(&self, word: &Word, span: Span) -> Result<Value> {
+        let error = |kind| EvalError { kind, span };
+        match word {
+            Word::Literal(literal) => Ok(Value::String(literal.clone())),
+            Word::PathLiteral(pattern) => {
+                let home = self.home_dir();
+                let paths = expand_path(pattern, &self.cwd, home.as_deref())
+                    .map_err(error)?;
+                if has_glob(pattern) {
+                    Ok(Value::Array(
+                        paths.into_iter().map(Value::Path).collect(),
+                    ))
+                }
+                else {
+                    Ok(paths.into_iter().map(Value::Path).next().unwrap())
+                }
+            }
+            Word::SpecialVar(var) => Ok(self.special_var(var)),
+            Word::EnvVar(name) => {
+                match self.env_var(name).map(OsString::into_string) {
+                    Some(Ok(value)) => Ok(Value::some(Value::String(value))),
+                    None => Ok(Value::none()),
+                    Some(Err(_)) => Err(error(
+                        EvalErrorKind::NotUnicodeEnvVar(name.clone()),
+                    )),
+                }
+            }
+            Word::ShellVar(name) => {
+                self.vars.get(name).map(|var| var.value.clone()).ok_or_else(
+                    || error(EvalErrorKind::UndefinedVariable(name.clone())),
+                )
+            }
+            Word::Postfix(operand, op) => {
+                self.apply_postfix(self.eval_word(operand)?, op, operand)
+            }
+            Word::UnwrapOr(operand, default) => {
+                match into_option(self.eval_word(operand)?, operand.span())? {
+                    Some(value) => Ok(value),
+                    None => self.eval_word(default),
+                }
+            }
+            Word::Expr(expr) => self.eval_expr(expr),
+        }
+    }
+    /// 後置演算子を適用する
+    ///
+    /// `operand`はエラー表示に使う
+    fn apply_postfix(
+        &self,
+        value: Value,
+        op: &Postfix,
+        operand: &Spanned<impl Display>,
+    ) -> Result<Value> {
+        match op {
+            Postfix::IsSome => {
+                Ok(Value::Bool(into_option(value, operand.span())?.is_some()))
+            }
+            Postfix::Unwrap => {
+                into_option(value, operand.span())?.ok_or_else(|| EvalError {
+                    kind: EvalErrorKind::UnwrapNone(operand.to_string()),
+                    span: operand.span(),
+                })
+            }
+            // パスは展開後の文字数
+            Postfix::Len => match value {
+                Value::String(string) => {
+                    Ok(Value::Int(string.chars().count() as i64))
+                }
+                Value::Array(values) => Ok(Value::Int(values.len() as i64)),
+                Value::Path(path) => Ok(Value::Int(
+                    path.to_string_lossy().chars().count() as i64,
+                )),
+                value => Err(EvalError {
+                    kind: EvalErrorKind::NoLength(value.ty()),
+                    span: operand.span(),
+                }),
+            },
+            // 範囲外はnoneになる
+            Postfix::Index(index) => {
+                let Value::Array(mut values) = value
+                else {
+                    return Err(type_mismatch(operand.span(), "array", &value));
+                };
+                let index = self.eval_int(index)?;
+                Ok(match element_index(index, values.len()) {
+                    Some(i) => Value::some(values.swap_remove(i)),
+                    None => Value::none(),
+                })
+            }
+        }
+    }
+    /// 配列のインデックスや範囲などのint型の式を評価する
+    pub(super) fn eval_int(&self, index: &Spanned<Expr>) -> Result<i64> {
+        match self.eval_expr(index)? {
+            Value::Int(index) => Ok(index),
+            value => Err(type_mismatch(index.span(), "int", &value)),
+        }
+    }
+    /// 式を評価する
+    pub(super) fn eval_expr(&self, expr: &Spanned<Expr>) -> Result<Value> {
+        let error = |kind| EvalError {
+            kind,
+            span: expr.span(),
+        };
+        match expr.inner() {
+            Expr::Word(word) => self.eval_word_at(word, expr.span()),
+            Expr::Int(int) => Ok(Value::Int(*int)),
+            Expr::Float(float) => Ok(Value::Float(float.0)),
+            Expr::Bool(bool) => Ok(Value::Bool(*bool)),
+            Expr::Unary(op, operand) => match (op, self.eval_expr(operand)?) {
+                (UnaryOp::Neg, Value::Int(int)) => int
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or(error(EvalErrorKind::Overflow)),
+                (UnaryOp::Neg, Value::Float(float)) => Ok(Value::Float(-float)),
+                (UnaryOp::Not, Value::Bool(bool)) => Ok(Value::Bool(!bool)),
+                (op, value) => Err(error(EvalErrorKind::InvalidUnaryOperand {
+                    op: *op,
+                    operand: value.ty(),
+                })),
+            },
+            Expr::Binary(left, op, right) => {
+                self.eval_binary(left, *op, right, expr.span())
+            }
+            Expr::Postfix(operand, op) => {
+                self.apply_postfix(self.eval_expr(operand)?, op, operand)
+            }
+            Expr::UnwrapOr(operand, default) => {
+                match into_option(self.eval_expr(operand)?, operand.span())? {
+                    Some(value) => Ok(value),
+                    None => self.eval_expr(default),
+                }
+            }
+            Expr::As(operand, ty) => {
+                cast(self.eval_expr(operand)?, ty).map_err(error)
+            }
+            // 要素の型はすべて同じ
+            Expr::Array(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    let value = self.eval_expr(element)?;
+                    if let Some(first) = values.first().map(Value::ty)
+                        && !first.accepts(&value.ty())
+                    {
+                        return Err(EvalError {
+                            kind: EvalErrorKind::TypeMismatch {
+                                expected: first.name(),
+                                found: value.ty(),
+                            },
+                            span: element.span(),
+                        });
+                    }
+                    values.push(value);
+                }
+                Ok(Value::Array(values))
+            }
+            Expr::Some(value) => Ok(Value::some(self.eval_expr(value)?)),
+            Expr::None => Ok(Value::none()),
+        }
+    }
+    /// 二項演算を評価する
+    ///
+    /// 演算のエラーは式全体の位置`span`で報告する
+    fn eval_binary(
+        &self,
+        left: &Spanned<Expr>,
+        op: BinaryOp,
+        right: &Spanned<Expr>,
+        span: Span,
+    ) -> Result<Value> {
+        use BinaryOp::*;
+        use Value::{Bool, Float, Int};
+        // 論理演算は短絡評価する
+        if let And | Or = op {
+            let eval_bool = |expr: &Spanned<Expr>| match self.eval_expr(expr) {
+                Ok(Bool(bool)) => Ok(bool),
+                Ok(value) => Err(type_mismatch(expr.span(), "bool", &value)),
+                Err(e) => Err(e),
+            };
+            let left = eval_bool(left)?;
+            if left == (op == Or) {
+                return Ok(Bool(left));
+            }
+            return eval_bool(right).map(Bool);
+        }
+        let left = self.eval_expr(left)?;
+        let right = self.eval_expr(right)?;
+        let overflow = EvalErrorKind::Overflow;
+        let result = match (op, left, right) {
+            (Div | Rem, Int(_), Int(0)) => Err(EvalErrorKind::DivisionByZero),
+            (Add, Int(a), Int(b)) => a.checked_add(b).map(Int).ok_or(overflow),
+            (Sub, Int(a), Int(b)) => a.checked_sub(b).map(Int).ok_or(overflow),
+            (Mul, Int(a), Int(b)) => a.checked_mul(b).map(Int).ok_or(overflow),
+            (Div, Int(a), Int(b)) => a.checked_div(b).map(Int).ok_or(overflow),
+            (Rem, Int(a), Int(b)) => a.checked_rem(b).map(Int).ok_or(overflow),
+            (Add, Float(a), Float(b)) => Ok(Float(a + b)),
+            (Sub, Float(a), Float(b)) => Ok(Float(a - b)),
+            (Mul, Float(a), Float(b)) => Ok(Float(a * b)),
+            (Div, Float(a), Float(b)) => Ok(Float(a / b)),
+            (Rem, Float(a), Float(b)) => Ok(Float(a % b)),
+            (Add, Value::String(a), Value::String(b)) => {
+                Ok(Value::String(a + &b))
+            }
+            // 末尾に追加した新しい配列
+            (Add, Value::Array(mut values), value)
+                if values
+                    .first()
+                    .is_none_or(|first| first.ty().accepts(&value.ty())) =>
+            {
+                values.push(value);
+                Ok(Value::Array(values))
+            }
+            (Eq | Ne, left, right) if left.ty().accepts(&right.ty()) => {
+                Ok(Bool((left == right) == (op == Eq)))
+            }
+            (Lt | Le | Gt | Ge, Int(a), Int(b)) => {
+                Ok(compare(op, a.partial_cmp(&b)))
+            }
+            (Lt | Le | Gt | Ge, Float(a), Float(b)) => {
+                Ok(compare(op, a.partial_cmp(&b)))
+            }
+            (Lt | Le | Gt | Ge, Value::String(a), Value::String(b)) => {
+                Ok(compare(op, a.partial_cmp(&b)))
+            }
+            (op, left, right) => Err(EvalErrorKind::InvalidBinaryOperands {
+                op,
+                left: left.ty(),
+                right: right.ty(),
+            }),
+        };
+        result.map_err(|kind| EvalError { kind, span })
+    }
+    fn special_var(&self, var: &SpecialVar) -> Value {
+        match var {
+            SpecialVar::ExitStatus => Value::Int(self.last_status().into()),
+            SpecialVar::Pid => Value::Int(std::process::id().into()),
+            SpecialVar::BackgroundPid => Value::Option(
+                self.last_background_pid
+                    .map(|pid| Box::new(Value::Int(pid.into()))),
+            ),
+            SpecialVar::ShellName => Value::String(self.shell_name.clone()),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+/// 外部コマンドのフルパスのキャッシュ
+///
+/// `PATH`が変わった場合はキャッシュを破棄する
+#[derive(Clone, Debug, Default)]
+pub struct CommandTable {
+    path: Option<OsString>,
+    commands: HashMap<OsString, PathBuf>,
+}
+impl CommandTable {
+    /// `PATH`からコマンドを探す
+    ///
+    /// パスで指定された場合は`cwd`からの相対パスとして扱う
+    pub fn find(
+        &mut self,
+        name: &OsStr,
+        path: Option<&OsStr>,
+        cwd: &Path,
+    ) -> Option<PathBuf> {
+        if has_parent(Path::new(name)) {
+            return find_executable(cwd.join(name).as_os_str(), path);
+        }
+        self.find_in(name, path)
+    }
+    pub fn find_in(
+        &mut self,
+        name: &OsStr,
+        path: Option<&OsStr>,
+    ) -> Option<PathBuf> {
+        if self.path.as_deref() != path {
+            self.commands.clear();
+            self.path = path.map(OsStr::to_owned);
+        }
+        // パスで指定された場合はキャッシュしない
+        if has_parent(Path::new(name)) {
+            return find_executable(name, path);
+        }
+        // 削除されたコマンドは探し直す
+        if let Some(cached) = self.commands.get(name)
+            && is_executable(cached)
+        {
+            return Some(cached.clone());
+        }
+        let found = find_executable(name, path)?;
+        self.commands.insert(name.to_owned(), found.clone());
+        Some(found)
+    }
+}
+
+/// 実行可能ファイルのフルパスを探索
+pub fn find_executable(name: &OsStr, path: Option<&OsStr>) -> Option<PathBuf> {
+    let name = PathBuf::from(name);
+
+    // 探索するパスを取得
+    let search_dirs = if has_parent(&name) {
+        vec![PathBuf::new()]
+    }
+    else {
+        path.map(|path| std::env::split_paths(path).collect())
+            .unwrap_or_default()
+    };
+
+    for dir in search_dirs {
+        let candidate = dir.join(&name);
+        #[cfg(windows)]
+        {
+            if let Some(candidate) = with_pathext(&candidate) {
+                return Some(candidate);
+            }
+        }
+        #[cfg(not(windows))]
+        {
+            if is_executable(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+fn has_parent(name: &Path) -> bool {
+    name.parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| {
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    })
+}
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// 拡張子を補完して実行可能ファイルを探す
+#[cfg(windows)]
+fn with_pathext(candidate: &Path) -> Option<PathBuf> {
+    if candidate.extension().is_some() {
+        return is_executable(candidate).then(|| candidate.to_owned());
+    }
+    get_pathext()
+        .into_iter()
+        .map(|ext| candidate.with_extension(ext))
+        .find(|candidate| is_executable(candidate))
+}
+#[cfg(windows)]
+fn get_pathext() -> Vec<OsString> {
+    std::env::var_os("PATHEXT")
+        .unwrap_or_else(|| OsString::from(".COM;.EXE;.BAT;.CMD"))
+        .to_string_lossy()
+        .split(';')
+        .map(|s| OsString::from(s.trim_start_matches('.')))
+        .collect()
+}
@@ -0,0 +1,191 @@
+use super::EvalErrorKind;
+use std::path::{Path, PathBuf, is_separator};
+
+/// パスリテラルにグロブが含まれるか
+pub fn has_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// パスリテラルのチルダ展開とグロブ展開を行う
+///
+/// グロブを含む場合は一致したパスを整列して返す
+/// 相対パスは`cwd`から探索するが、相対パスのまま返す
+pub fn expand_path(
+    pattern: &str,
+    cwd: &Path,
+    home: Option<&Path>,
+) -> Result<Vec<PathBuf>, EvalErrorKind> {
+    let (base, rest) = expand_tilde(pattern, home)?;
+    if !has_glob(rest) {
+        return Ok(vec![if rest.is_empty() {
+            base
+        }
+        else {
+            base.join(rest)
+        }]);
+    }
+
+    let mut paths = vec![base];
+    for component in rest.split(is_separator).filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for path in &paths {
+            if component == "**" {
+                next.push(path.clone());
+                descendants(path, cwd, &mut next);
+            }
+            else if !has_glob(component) {
+                let candidate = path.join(component);
+                if cwd.join(&candidate).symlink_metadata().is_ok() {
+                    next.push(candidate);
+                }
+            }
+            else {
+                let pattern: Vec<char> = component.chars().collect();
+                for name in read_dir(&cwd.join(path)) {
+                    let chars: Vec<char> =
+                        name.to_string_lossy().chars().collect();
+                    // 隠しファイルは.から始まるパターンにのみ一致する
+                    if chars.first() == Some(&'.') && pattern[0] != '.' {
+                        continue;
+                    }
+                    if matches(&pattern, &chars) {
+                        next.push(path.join(name));
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+
+    paths.retain(|path| !path.as_os_str().is_empty());
+    paths.sort();
+    paths.dedup();
+    if paths.is_empty() {
+        return Err(EvalErrorKind::NoGlobMatch(pattern.to_string()));
+    }
+    Ok(paths)
+}
+
+/// 先頭の`~`と`~user`をホームディレクトリに展開する
+fn expand_tilde<'a>(
+    pattern: &'a str,
+    home: Option<&Path>,
+) -> Result<(PathBuf, &'a str), EvalErrorKind> {
+    let Some(tilde) = pattern.strip_prefix('~')
+    else {
+        // 絶対パスはルートから探索する
+        return Ok(match pattern.find(|c| !is_separator(c)) {
+            Some(0) => (PathBuf::new(), pattern),
+            Some(i) => (PathBuf::from(&pattern[..i]), &pattern[i..]),
+            None => (PathBuf::from(pattern), ""),
+        });
+    };
+    let end = tilde.find(is_separator).unwrap_or(tilde.len());
+    let (user, rest) = tilde.split_at(end);
+    let rest = rest.trim_start_matches(is_separator);
+    let home = if user.is_empty() {
+        home.ok_or(EvalErrorKind::HomeDirNotFound)?.to_path_buf()
+    }
+    else {
+        user_home_dir(user)
+            .ok_or_else(|| EvalErrorKind::UnknownUser(user.to_string()))?
+    };
+    Ok((home, rest))
+}
+/// 他のユーザーのホームディレクトリ
+#[cfg(unix)]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<_> = line.split(':').collect();
+        (fields.len() == 7 && fields[0] == user)
+            .then(|| PathBuf::from(fields[5]))
+    })
+}
+#[cfg(not(unix))]
+fn user_home_dir(user: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?.parent()?.join(user);
+    home.is_dir().then_some(home)
+}
+
+fn read_dir(dir: &Path) -> Vec<std::ffi::OsString> {
+    // 読めないディレクトリは空として扱う
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.file_name()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+/// `**`に一致する子孫のパス（隠しディレクトリは辿らない）
+fn descendants(path: &Path, cwd: &Path, paths: &mut Vec<PathBuf>) {
+    for name in read_dir(&cwd.join(path)) {
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let child = path.join(name);
+        let is_dir = cwd
+            .join(&child)
+            .symlink_metadata()
+            .is_ok_and(|m| m.is_dir());
+        paths.push(child.clone());
+        if is_dir {
+            descendants(&child, cwd, paths);
+        }
+    }
+}
+
+/// 文字列がグロブのパターンに一致するか
+pub fn glob_matches(pattern: &str, string: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let string: Vec<char> = string.chars().collect();
+    matches(&pattern, &string)
+}
+/// ファイル名がグロブのパターンに一致するか
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => {
+            (0..=name.len()).any(|i| matches(rest, &name[i..]))
+        }
+        Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+        Some(('[', rest)) => match char_class(rest) {
+            Some((class, rest)) => name
+                .split_first()
+                .is_some_and(|(c, name)| class(*c) && matches(rest, name)),
+            // 閉じられていない[は文字として扱う
+            None => name.first() == Some(&'[') && matches(rest, &name[1..]),
+        },
+        Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
+/// `[...]`の文字クラスを解析して、判定関数と残りのパターンを返す
+fn char_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool, &[char])> {
+    let (negate, pattern) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    // 先頭の]は文字として扱う
+    let end = pattern
+        .iter()
+        .skip(1)
+        .position(|c| *c == ']')
+        .map(|i| i + 1)?;
+    let (class, rest) = (&pattern[..end], &pattern[end + 1..]);
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            ranges.push(class[i]..=class[i + 2]);
+            i += 3;
+        }
+        else {
+            ranges.push(class[i]..=class[i]);
+            i += 1;
+        }
+    }
+    let class =
+        move |c| ranges.iter().any(|range| range.contains(&c)) != negate;
+    Some((class, rest))
+}
@@ -0,0 +1,225 @@
+use super::{
+    Error, EvalError, EvalErrorKind, Result, Shell,
+    eval::cast,
+    stream::Streams,
+    var::{Var, coerce},
+};
+use crate::{
+    parse::{Function, Span, Spanned, Word, error::Source},
+    value::{Type, Value},
+};
+use std::{ffi::OsString, sync::Arc};
+
+/// 関数の呼び出しの深さの上限
+pub const MAX_CALL_DEPTH: usize = 200;
+/// 上限まで関数を呼び出してもあふれないシェルのスレッドのスタックの大きさ
+///
+/// デバッグビルドでブロックを入れ子にした関数でも足りるようにする
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// 定義された関数
+#[derive(Debug)]
+pub struct FunctionDef {
+    function: Function,
+    /// エラー表示に使う、定義したときの入力
+    text: String,
+    origin: Option<(String, usize)>,
+}
+impl FunctionDef {
+    fn source(&self) -> Source<'_> {
+        Source {
+            text: &self.text,
+            origin: self
+                .origin
+                .as_ref()
+                .map(|(file, line)| (file.as_str(), *line)),
+        }
+    }
+}
+
+impl Shell {
+    /// 関数を定義する
+    ///
+    /// 同じ名前の関数があれば上書きする
+    pub(super) fn define_function(
+        &mut self,
+        function: &Function,
+        source: Source,
+    ) {
+        let def = FunctionDef {
+            function: function.clone(),
+            text: source.text.to_string(),
+            origin: source.origin.map(|(file, line)| (file.to_string(), line)),
+        };
+        self.functions
+            .insert(function.name.inner().clone(), Arc::new(def));
+    }
+    /// 関数を呼び出して終了ステータスを返す
+    ///
+    /// `args`は展開した引数とその単語の位置、`span`は関数名の位置
+    /// 関数の中からは引数の変数だけが見え、呼び出し元の変数は見えない
+    /// 関数の中で代入した変数は呼び出しの後で捨てる
+    pub(super) fn call_function(
+        &mut self,
+        def: &FunctionDef,
+        args: Vec<(OsString, Span)>,
+        span: Span,
+        streams: Streams,
+    ) -> Result<i32> {
+        let function = &def.function;
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(EvalError {
+                kind: EvalErrorKind::RecursionLimit(
+                    function.name.inner().clone(),
+                ),
+                span,
+            }
+            .into());
+        }
+        let vars = std::mem::take(&mut self.vars);
+        self.call_depth += 1;
+        let result = self.bind_args(function, args, span).and_then(|()| {
+            self.with_io(streams, |shell| {
+                shell.execute_block(&function.block, def.source())
+            })
+        });
+        self.call_depth -= 1;
+        self.vars = vars;
+        match result {
+            Err(Error::Return(status)) => Ok(status),
+            result => result,
+        }
+    }
+    /// 引数を宣言された型に変換して引数の変数にする
+    ///
+    /// 足りない引数はデフォルト値にし、option型ならnoneにする
+    /// デフォルト値の式ではそれより前の引数を使える
+    fn bind_args(
+        &mut self,
+        function: &Function,
+        args: Vec<(OsString, Span)>,
+        span: Span,
+    ) -> Result<()> {
+        let mut args = args.into_iter().peekable();
+        for param in &function.params {
+            let ty = param.ty.inner();
+            let name = param.name.inner();
+            let value = match (ty, args.peek(), &param.default) {
+                // 最後の配列型の引数は残りをすべて受け取る
+                (Type::Array(element), Some(_), _)
+                | (Type::Array(element), None, None) => {
+                    let values = args
+                        .by_ref()
+                        .map(|(arg, span)| parse_arg(arg, element, span))
+                        .collect::<Result<_>>()?;
+                    Value::Array(values)
+                }
+                (_, Some(_), _) => {
+                    let (arg, span) = args.next().unwrap();
+                    parse_arg(arg, ty, span)?
+                }
+                (_, None, Some(default)) => {
+                    coerce(ty, self.eval_expr(default)?).map_err(|value| {
+                        EvalError {
+                            kind: EvalErrorKind::VarTypeMismatch {
+                                name: format!("%{name}"),
+                                expected: ty.clone(),
+                                found: value.ty(),
+                            },
+                            span: default.span(),
+                        }
+                    })?
+                }
+                (Type::Option(_), None, None) => Value::none(),
+                (_, None, None) => {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::MissingArgument(format!(
+                            "%{name}"
+                        )),
+                        span,
+                    }
+                    .into());
+                }
+            };
+            self.vars.insert(
+                name.clone(),
+                Var {
+                    ty: ty.clone(),
+                    value,
+                },
+            );
+        }
+        if let Some((_, span)) = args.next() {
+            return Err(EvalError {
+                kind: EvalErrorKind::TooManyArguments(
+                    function.name.inner().clone(),
+                ),
+                span,
+            }
+            .into());
+        }
+        Ok(())
+    }
+    /// `return`の値から関数の終了ステータスを決める
+    ///
+    /// 値がなければ直前の終了ステータス、intならその値、boolなら真で0・偽で1にする
+    /// それ以外の値は`echo`のように出力して0にする
+    pub(super) fn return_status(
+        &mut self,
+        value: &Option<Spanned<Word>>,
+    ) -> Result<i32> {
+        let Some(word) = value
+        else {
+            return Ok(self.last_status());
+        };
+        match self.eval_word(word)? {
+            Value::Int(status) => i32::try_from(status).map_err(|_| {
+                EvalError {
+                    kind: EvalErrorKind::Overflow,
+                    span: word.span(),
+                }
+                .into()
+            }),
+            Value::Bool(bool) => Ok(if bool { 0 } else { 1 }),
+            value => {
+                let mut args = Vec::new();
+                value.into_args(&mut args);
+                let mut output = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        output.push(b' ');
+                    }
+                    output.extend_from_slice(arg.as_encoded_bytes());
+                }
+                output.push(b'\n');
+                self.io
+                    .stdout
+                    .try_clone()
+                    .and_then(|stdout| stdout.write_output(output))
+                    .map_err(super::io_error)?;
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// コマンドの引数を型`ty`の値に変換する
+fn parse_arg(arg: OsString, ty: &Type, span: Span) -> Result<Value> {
+    match ty {
+        Type::Path => Ok(Value::Path(arg.into())),
+        Type::Option(inner) => parse_arg(arg, inner, span).map(Value::some),
+        _ => {
+            let kind = match arg.into_string() {
+                Ok(arg) => match cast(Value::String(arg), ty) {
+                    Ok(value) => return Ok(value),
+                    Err(kind) => kind,
+                },
+                Err(arg) => EvalErrorKind::CastFailed {
+                    value: arg.to_string_lossy().into_owned(),
+                    to: ty.clone(),
+                },
+            };
+            Err(EvalError { kind, span }.into())
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    fs::File,
+    io::{PipeReader, PipeWriter, Write},
+    process::Stdio,
+};
+
+/// コマンドの標準入出力の接続先
+#[derive(Debug)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+    PipeReader(PipeReader),
+    PipeWriter(PipeWriter),
+}
+impl Stream {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        use Stream::*;
+        Ok(match self {
+            Stdin => Stdin,
+            Stdout => Stdout,
+            Stderr => Stderr,
+            File(file) => File(file.try_clone()?),
+            PipeReader(reader) => PipeReader(reader.try_clone()?),
+            PipeWriter(writer) => PipeWriter(writer.try_clone()?),
+        })
+    }
+    /// 外部コマンドに渡す
+    pub fn into_stdio(self) -> Stdio {
+        use Stream::*;
+        match self {
+            Stdin => Stdio::inherit(),
+            Stdout => std::io::stdout().into(),
+            Stderr => std::io::stderr().into(),
+            File(file) => file.into(),
+            PipeReader(reader) => reader.into(),
+            PipeWriter(writer) => writer.into(),
+        }
+    }
+    /// ビルトインの出力を書き込む
+    ///
+    /// パイプへの書き込みは読み手を待たないよう別スレッドで行う
+    pub fn write_output(self, buffer: Vec<u8>) -> std::io::Result<()> {
+        use Stream::*;
+        match self {
+            Stdout => {
+                let mut stdout = std::io::stdout();
+                stdout.write_all(&buffer)?;
+                stdout.flush()
+            }
+            Stderr => std::io::stderr().write_all(&buffer),
+            File(mut file) => file.write_all(&buffer),
+            PipeWriter(mut writer) => {
+                std::thread::spawn(move || {
+                    // 読み手が先に終了した場合は書き込みに失敗するが無視する
+                    let _ = writer.write_all(&buffer);
+                });
+                Ok(())
+            }
+            Stdin | PipeReader(_) => {
+                if buffer.is_empty() {
+                    Ok(())
+                }
+                else {
+                    Err(std::io::Error::other("入力には書き込めません"))
+                }
+            }
+        }
+    }
+}
+
+/// コマンドの標準入力・標準出力・標準エラー出力
+#[derive(Debug)]
+pub struct Streams {
+    pub stdin: Stream,
+    pub stdout: Stream,
+    pub stderr: Stream,
+}
+impl Default for Streams {
+    /// シェル自身の標準入出力
+    fn default() -> Self {
+        Streams {
+            stdin: Stream::Stdin,
+            stdout: Stream::Stdout,
+            stderr: Stream::Stderr,
+        }
+    }
+}
+impl Streams {
+    pub fn get(&self, fd: u32) -> &Stream {
+        match fd {
+            0 => &self.stdin,
+            1 => &self.stdout,
+            _ => &self.stderr,
+        }
+    }
+    pub fn set(&mut self, fd: u32, stream: Stream) {
+        match fd {
+            0 => self.stdin = stream,
+            1 => self.stdout = stream,
+            _ => self.stderr = stream,
+        }
+    }
+}
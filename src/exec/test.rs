@@ -0,0 +1,1904 @@
+use super::{executable::*, var::Var, *};
+use crate::{
+    parse::{BinaryOp, Command, Span, UnaryOp},
+    value::Type,
+};
+use std::path::{Path, PathBuf};
+
+fn run(shell: &mut Shell, line: &str) {
+    let command = crate::parse::parse_shell_command(line).unwrap();
+    shell.execute(&command, line).unwrap();
+}
+/// 型を検査する
+fn check(shell: &Shell, line: &str) -> std::result::Result<(), EvalError> {
+    shell.check(&crate::parse::parse_shell_command(line).unwrap())
+}
+/// 型の検査や評価のエラー
+fn error<T>(
+    kind: EvalErrorKind,
+    span: Span,
+) -> std::result::Result<T, EvalError> {
+    Err(EvalError { kind, span })
+}
+/// シェル変数の値
+fn value(shell: &Shell, name: &str) -> Option<Value> {
+    shell.vars.get(name).map(|var| var.value.clone())
+}
+/// 終わらなければテストを止めずに失敗させるよう、別スレッドで実行する
+fn run_with_timeout(mut shell: Shell, line: &str) -> Shell {
+    let line = line.to_string();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        run(&mut shell, &line);
+        let _ = sender.send(shell);
+    });
+    receiver
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("時間内に終わりませんでした")
+}
+
+/// テスト用の一時ディレクトリ
+struct TempDir(PathBuf);
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir()
+            .join(format!("asari-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+    fn path(&self) -> &Path {
+        &self.0
+    }
+    #[cfg(unix)]
+    fn file(&self, name: &str, mode: u32) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = self.0.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+            .unwrap();
+        path
+    }
+}
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn find_executable_test() {
+    let temp = TempDir::new("find_executable");
+    let bin = temp.path().join("bin");
+    let local = temp.path().join("local");
+    let tool = temp.file("bin/tool", 0o755);
+    let local_tool = temp.file("local/tool", 0o755);
+    let only_local = temp.file("local/only_local", 0o700);
+    temp.file("bin/not_executable", 0o644);
+    std::fs::create_dir_all(bin.join("directory")).unwrap();
+    let path = std::env::join_paths([&bin, &local]).unwrap();
+
+    // PATHの前にあるディレクトリが優先される
+    assert_eq!(
+        find_executable("tool".as_ref(), Some(&path)),
+        Some(tool.clone())
+    );
+    assert_eq!(
+        find_executable("only_local".as_ref(), Some(&path)),
+        Some(only_local.clone())
+    );
+
+    // 実行権限のないファイルとディレクトリは無視する
+    assert_eq!(
+        find_executable("not_executable".as_ref(), Some(&path)),
+        None
+    );
+    assert_eq!(find_executable("directory".as_ref(), Some(&path)), None);
+    assert_eq!(find_executable("missing".as_ref(), Some(&path)), None);
+    assert_eq!(find_executable("tool".as_ref(), None), None);
+
+    // 拡張子は補完しない
+    assert_eq!(find_executable("tool.EXE".as_ref(), Some(&path)), None);
+
+    // パスで指定した場合はPATHを探索しない
+    assert_eq!(
+        find_executable(local_tool.as_os_str(), Some(&path)),
+        Some(local_tool.clone())
+    );
+    let not_executable = bin.join("not_executable");
+    assert_eq!(
+        find_executable(not_executable.as_os_str(), Some(&path)),
+        None
+    );
+    let empty = std::env::join_paths([&local]).unwrap();
+    assert_eq!(
+        find_executable(tool.as_os_str(), Some(&empty)),
+        Some(tool.clone())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn command_table_test() {
+    let temp = TempDir::new("command_table");
+    let bin = temp.path().join("bin");
+    let local = temp.path().join("local");
+    let tool = temp.file("bin/tool", 0o755);
+    let local_tool = temp.file("local/tool", 0o755);
+    let path = std::env::join_paths([&bin, &local]).unwrap();
+    let local_only = std::env::join_paths([&local]).unwrap();
+
+    let mut table = CommandTable::default();
+    assert_eq!(
+        table.find_in("tool".as_ref(), Some(&path)),
+        Some(tool.clone())
+    );
+
+    // PATHが変わるとキャッシュを破棄する
+    assert_eq!(
+        table.find_in("tool".as_ref(), Some(&local_only)),
+        Some(local_tool.clone())
+    );
+    assert_eq!(
+        table.find_in("tool".as_ref(), Some(&path)),
+        Some(tool.clone())
+    );
+
+    // キャッシュされたコマンドが削除された場合は探し直す
+    std::fs::remove_file(&tool).unwrap();
+    assert_eq!(
+        table.find_in("tool".as_ref(), Some(&path)),
+        Some(local_tool.clone())
+    );
+    std::fs::remove_file(&local_tool).unwrap();
+    assert_eq!(table.find_in("tool".as_ref(), Some(&path)), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_status_test() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.last_status(), 0);
+
+    // 外部コマンド
+    run(&mut shell, "false");
+    assert_eq!(shell.last_status(), 1);
+    run(&mut shell, "true");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "sh -c 'exit 42'");
+    assert_eq!(shell.last_status(), 42);
+
+    // シグナルで終了した場合は128+シグナル番号
+    run(&mut shell, "sh -c 'kill -TERM $$'");
+    assert_eq!(shell.last_status(), 128 + 15);
+    run(&mut shell, "sh -c 'kill -KILL $$'");
+    assert_eq!(shell.last_status(), 128 + 9);
+
+    // ビルトインと失敗したコマンド
+    run(&mut shell, "cd .");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "mkdir");
+    assert_eq!(shell.last_status(), 1);
+    run(&mut shell, "asari-command-not-found");
+    assert_eq!(shell.last_status(), 127);
+
+    // パイプラインは最後のコマンドの終了ステータス
+    run(&mut shell, "false | true");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "true | false");
+    assert_eq!(shell.last_status(), 1);
+
+    // パイプラインの中のexitはその段だけを終了する
+    run(&mut shell, "echo hi | exit 3");
+    assert_eq!(shell.last_status(), 3);
+    run(&mut shell, "exit 4 | cat; sh -c 'exit 5'");
+    assert_eq!(shell.last_status(), 5);
+    let command = crate::parse::parse_shell_command("exit 6").unwrap();
+    assert!(matches!(
+        shell.execute(&command, "exit 6"),
+        Err(Error::Exit(6))
+    ));
+
+    // 区切られたコマンドは最後のコマンドの終了ステータス
+    run(&mut shell, "false; true");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "true; asari-command-not-found");
+    assert_eq!(shell.last_status(), 127);
+}
+
+#[cfg(unix)]
+#[test]
+fn special_var_test() {
+    let temp = TempDir::new("special_var");
+    let out = temp.path().join("out.txt");
+    let out = out.to_str().unwrap();
+    let mut shell = Shell::new();
+
+    // $?は直前のコマンドの終了ステータス
+    run(&mut shell, "sh -c 'exit 3'; sh -c 'exit $1' status $?");
+    assert_eq!(shell.last_status(), 3);
+    run(&mut shell, &format!("sh -c 'exit 5'; echo $? > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "5\n");
+
+    // $$はシェル自身のPID
+    run(&mut shell, &format!("echo $$ > {out}"));
+    assert_eq!(
+        std::fs::read_to_string(out).unwrap(),
+        format!("{}\n", std::process::id())
+    );
+
+    // $@はシェル名
+    run(&mut shell, &format!("echo $@ > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "asari\n");
+
+    // $!はバックグラウンドジョブがなければ展開されない
+    run(&mut shell, &format!("echo a $! b > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a b\n");
+}
+
+/// 最初のコマンドを解析する
+fn parse_command(line: &str) -> Command {
+    let command = crate::parse::parse_shell_command(line).unwrap();
+    match command.commands.into_iter().next() {
+        Some((Statement::Command(command), _)) => command,
+        _ => panic!("コマンドではありません : {line}"),
+    }
+}
+/// 最初のコマンドの最初の引数を評価する
+fn eval(shell: &Shell, line: &str) -> std::result::Result<Value, EvalError> {
+    shell.eval_word(&parse_command(line).args[0])
+}
+/// コマンドの引数を展開する
+fn expand(
+    shell: &Shell,
+    line: &str,
+) -> std::result::Result<Vec<OsString>, EvalError> {
+    let command = parse_command(line);
+    let mut args = Vec::new();
+    for word in &command.args {
+        args.extend(shell.expand_word(word)?);
+    }
+    Ok(args)
+}
+
+#[test]
+fn expand_word_test() {
+    let mut shell = Shell::new();
+    let strings = |strings: &[&str]| {
+        Ok(strings.iter().map(OsString::from).collect::<Vec<_>>())
+    };
+
+    // リテラルは単語分割しない
+    assert_eq!(expand(&shell, "echo a b"), strings(&["a", "b"]));
+    assert_eq!(expand(&shell, "echo 'a b'"), strings(&["a b"]));
+    assert_eq!(expand(&shell, r#"echo "$HOME""#), strings(&["$HOME"]));
+    assert_eq!(expand(&shell, "echo p\"a b/c\""), strings(&["a b/c"]));
+
+    // 環境変数は存在しなければ展開されない
+    run(&mut shell, "$ASARI_TEST_EXPAND = 'a b'");
+    run(&mut shell, "$ASARI_TEST_UNDEFINED = none");
+    assert_eq!(expand(&shell, "echo $ASARI_TEST_EXPAND"), strings(&["a b"]));
+    assert_eq!(expand(&shell, "echo $ASARI_TEST_UNDEFINED"), strings(&[]));
+
+    // シェル変数
+    shell
+        .vars
+        .insert("var".to_string(), Value::String("a b c".to_string()).into());
+    shell.vars.insert(
+        "files".to_string(),
+        Value::Array(vec![
+            Value::String("a.txt".to_string()),
+            Value::String("b c.txt".to_string()),
+        ])
+        .into(),
+    );
+    shell
+        .vars
+        .insert("empty".to_string(), Value::Array(Vec::new()).into());
+    assert_eq!(expand(&shell, "echo %var"), strings(&["a b c"]));
+    assert_eq!(
+        expand(&shell, "rm -f %files"),
+        strings(&["-f", "a.txt", "b c.txt"])
+    );
+    assert_eq!(expand(&shell, "rm %empty"), strings(&[]));
+
+    // 特殊変数
+    assert_eq!(expand(&shell, "echo $?"), strings(&["0"]));
+    assert_eq!(expand(&shell, "echo $!"), strings(&[]));
+
+    // 未定義のシェル変数はエラー
+    assert_eq!(
+        expand(&shell, "echo a %undefined"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("undefined".to_string()),
+            span: 7..17,
+        })
+    );
+}
+
+#[test]
+fn eval_error_display_test() {
+    let error = EvalError {
+        kind: EvalErrorKind::UndefinedVariable("変数".to_string()),
+        span: 7..14,
+    };
+    assert_eq!(
+        error.display("echo a %変数 b"),
+        "echo a %変数 b\n       ^^^ 未定義の変数 '変数' です\n"
+    );
+}
+
+#[test]
+fn expand_path_test() {
+    let temp = TempDir::new("expand_path");
+    let root = temp.path();
+    let home = root.join("home");
+    let expand =
+        |pattern: &str| super::expand::expand_path(pattern, root, Some(&home));
+    for file in [
+        "a.txt",
+        "b.txt",
+        "c.rs",
+        ".hidden.txt",
+        "dir/d.txt",
+        "dir/sub/e.txt",
+        "dir/.secret/f.txt",
+    ] {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    let pattern = |pattern: &str| format!("{}/{pattern}", root.display());
+    let paths =
+        |paths: &[&str]| Ok(paths.iter().map(|path| root.join(path)).collect());
+
+    // グロブを含まないパスは存在しなくてもそのまま
+    assert_eq!(expand(&pattern("new.txt")), paths(&["new.txt"]));
+    assert_eq!(expand("relative/path"), Ok(vec!["relative/path".into()]));
+
+    // 相対パスはカレントディレクトリから探して相対パスのまま返す
+    assert_eq!(expand("*.txt"), Ok(vec!["a.txt".into(), "b.txt".into()]));
+    assert_eq!(expand("dir/*.txt"), Ok(vec!["dir/d.txt".into()]));
+
+    // *と?は隠しファイルに一致しない
+    assert_eq!(expand(&pattern("*.txt")), paths(&["a.txt", "b.txt"]));
+    assert_eq!(expand(&pattern("?.rs")), paths(&["c.rs"]));
+    assert_eq!(
+        expand(&pattern("*")),
+        paths(&["a.txt", "b.txt", "c.rs", "dir"])
+    );
+    assert_eq!(expand(&pattern(".*.txt")), paths(&[".hidden.txt"]));
+
+    // 文字クラス
+    assert_eq!(expand(&pattern("[ab].txt")), paths(&["a.txt", "b.txt"]));
+    assert_eq!(expand(&pattern("[!a].txt")), paths(&["b.txt"]));
+    assert_eq!(expand(&pattern("[a-b].*")), paths(&["a.txt", "b.txt"]));
+
+    // 途中のディレクトリと**
+    assert_eq!(expand(&pattern("*/d.txt")), paths(&["dir/d.txt"]));
+    assert_eq!(
+        expand(&pattern("**/*.txt")),
+        paths(&["a.txt", "b.txt", "dir/d.txt", "dir/sub/e.txt"])
+    );
+    assert_eq!(
+        expand(&pattern("dir/**")),
+        paths(&["dir", "dir/d.txt", "dir/sub", "dir/sub/e.txt"])
+    );
+
+    // 一致しない場合はエラー
+    assert_eq!(
+        expand(&pattern("*.md")),
+        Err(EvalErrorKind::NoGlobMatch(pattern("*.md")))
+    );
+
+    // チルダ展開
+    assert_eq!(expand("~"), Ok(vec![home.clone()]));
+    assert_eq!(expand("~/file"), Ok(vec![home.join("file")]));
+    assert_eq!(expand("a/~"), Ok(vec!["a/~".into()]));
+    assert_eq!(
+        expand("~asari_no_such_user/file"),
+        Err(EvalErrorKind::UnknownUser("asari_no_such_user".to_string()))
+    );
+    assert_eq!(
+        super::expand::expand_path("~", root, None),
+        Err(EvalErrorKind::HomeDirNotFound)
+    );
+}
+
+#[test]
+fn path_literal_test() {
+    let temp = TempDir::new("path_literal");
+    std::fs::write(temp.path().join("a.txt"), "").unwrap();
+    std::fs::write(temp.path().join("b.txt"), "").unwrap();
+    let dir = temp.path().display();
+    let shell = Shell::new();
+
+    // グロブは複数の引数に展開される
+    assert_eq!(
+        expand(&shell, &format!("ls p\"{dir}/*.txt\"")),
+        Ok(vec![
+            temp.path().join("a.txt").into(),
+            temp.path().join("b.txt").into()
+        ])
+    );
+    let command = parse_command(&format!("ls p\"{dir}/*.txt\""));
+    assert_eq!(
+        shell.eval_word(&command.args[0]),
+        Ok(Value::Array(vec![
+            Value::Path(temp.path().join("a.txt")),
+            Value::Path(temp.path().join("b.txt")),
+        ]))
+    );
+
+    // 一致しない場合はパスリテラルの位置でエラー
+    let line = format!("ls p\"{dir}/*.md\"");
+    assert_eq!(
+        expand(&shell, &line),
+        Err(EvalError {
+            kind: EvalErrorKind::NoGlobMatch(format!("{dir}/*.md")),
+            span: 3..line.len(),
+        })
+    );
+}
+
+#[test]
+fn var_operator_test() {
+    let mut shell = Shell::new();
+    run(&mut shell, "$ASARI_TEST_OPERATOR = こんにちは");
+    run(&mut shell, "$ASARI_TEST_MISSING = none");
+
+    // 存在確認
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_OPERATOR?"),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_MISSING?"),
+        Ok(Value::Bool(false))
+    );
+
+    // unwrap
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_OPERATOR!"),
+        Ok(Value::String("こんにちは".to_string()))
+    );
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_MISSING!"),
+        Err(EvalError {
+            kind: EvalErrorKind::UnwrapNone("$ASARI_TEST_MISSING".to_string()),
+            span: 5..24,
+        })
+    );
+
+    // デフォルト値
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_OPERATOR ^ default"),
+        Ok(Value::String("こんにちは".to_string()))
+    );
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_MISSING ^ default"),
+        Ok(Value::String("default".to_string()))
+    );
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_MISSING ^ $ASARI_TEST_MISSING ^ x"),
+        Ok(Value::String("x".to_string()))
+    );
+
+    // 文字列長
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_OPERATOR!@"),
+        Ok(Value::Int(5))
+    );
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_MISSING!@"),
+        Err(EvalError {
+            kind: EvalErrorKind::UnwrapNone("$ASARI_TEST_MISSING".to_string()),
+            span: 5..24,
+        })
+    );
+
+    // 型が合わない
+    assert_eq!(
+        eval(&shell, "echo $ASARI_TEST_OPERATOR@"),
+        Err(EvalError {
+            kind: EvalErrorKind::NoLength(Type::Option(Box::new(Type::String))),
+            span: 5..25,
+        })
+    );
+    shell.vars.insert(
+        "name".to_string(),
+        Value::String("asari".to_string()).into(),
+    );
+    assert_eq!(
+        eval(&shell, "echo %name!"),
+        Err(EvalError {
+            kind: EvalErrorKind::TypeMismatch {
+                expected: "option",
+                found: Type::String,
+            },
+            span: 5..10,
+        })
+    );
+    assert_eq!(eval(&shell, "echo %name@"), Ok(Value::Int(5)));
+}
+
+#[test]
+fn assign_test() {
+    let mut shell = Shell::new();
+    let assign = |shell: &mut Shell, line: &str| {
+        let command = crate::parse::parse_shell_command(line).unwrap();
+        let Statement::Assign(assign) = &command.commands[0].0
+        else {
+            unreachable!()
+        };
+        shell.assign(assign)
+    };
+
+    // 環境変数は子プロセスに引き継がれる
+    run(&mut shell, "$ASARI_TEST_ASSIGN = \"a b\"");
+    assert_eq!(shell.env_var("ASARI_TEST_ASSIGN"), Some("a b".into()));
+    // プロセスの環境変数は変更しない
+    assert_eq!(std::env::var_os("ASARI_TEST_ASSIGN"), None);
+    #[cfg(unix)]
+    {
+        run(&mut shell, "sh -c 'test \"$ASARI_TEST_ASSIGN\" = \"a b\"'");
+        assert_eq!(shell.last_status(), 0);
+    }
+    run(&mut shell, "$ASARI_TEST_ASSIGN = none");
+    assert_eq!(shell.env_var("ASARI_TEST_ASSIGN"), None);
+
+    // 代入はunitになり、終了ステータスは0
+    run(&mut shell, "asari-test-not-found ; %x = 1");
+    assert_eq!(shell.last_status(), 0);
+    assert_eq!(assign(&mut shell, "%x = 2"), Ok(Value::Unit));
+
+    // シェル変数は型を推論する
+    assert_eq!(
+        shell.vars.get("x"),
+        Some(&Var {
+            ty: Type::String,
+            value: Value::String("2".to_string())
+        })
+    );
+    assert_eq!(eval(&shell, "echo %x"), Ok(Value::String("2".to_string())));
+    assert_eq!(
+        assign(&mut shell, "%x = $?"),
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: "%x".to_string(),
+                expected: Type::String,
+                found: Type::Int
+            },
+            span: 5..7
+        })
+    );
+
+    // 型注釈で宣言し直す
+    assign(&mut shell, "%x: int = $?").unwrap();
+    assert_eq!(eval(&shell, "echo %x"), Ok(Value::Int(0)));
+
+    // option型の変数にはsomeで包んで代入し、noneを代入しても残る
+    assign(&mut shell, "%opt: option<string> = a").unwrap();
+    assert_eq!(
+        eval(&shell, "echo %opt"),
+        Ok(Value::some(Value::String("a".to_string())))
+    );
+    assign(&mut shell, "%opt = none").unwrap();
+    assert_eq!(eval(&shell, "echo %opt?"), Ok(Value::Bool(false)));
+
+    // それ以外の変数はnoneで削除する
+    assign(&mut shell, "%x = none").unwrap();
+    assert_eq!(
+        eval(&shell, "echo %x"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("x".to_string()),
+            span: 5..7
+        })
+    );
+    // 型のない変数にはnoneを代入できない
+    assert_eq!(
+        assign(&mut shell, "%x = none"),
+        Err(EvalError {
+            kind: EvalErrorKind::UntypedNone,
+            span: 5..9
+        })
+    );
+
+    // 環境変数は文字列のみ
+    assert_eq!(
+        assign(&mut shell, "$ASARI_TEST_ASSIGN = $?"),
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: "$ASARI_TEST_ASSIGN".to_string(),
+                expected: Type::String,
+                found: Type::Int
+            },
+            span: 21..23
+        })
+    );
+}
+
+#[test]
+fn temp_env_test() {
+    let mut shell = Shell::new();
+    run(&mut shell, "$ASARI_TEST_TEMP = before");
+    run(&mut shell, "$ASARI_TEST_TEMP_NEW = none");
+
+    // 外部コマンドにだけ設定される
+    #[cfg(unix)]
+    {
+        run(
+            &mut shell,
+            "$ASARI_TEST_TEMP := after sh -c 'test \"$ASARI_TEST_TEMP\" = after'",
+        );
+        assert_eq!(shell.last_status(), 0);
+        run(
+            &mut shell,
+            "$ASARI_TEST_TEMP := none sh -c 'test -z \"${ASARI_TEST_TEMP+x}\"'",
+        );
+        assert_eq!(shell.last_status(), 0);
+    }
+    assert_eq!(shell.env_var("ASARI_TEST_TEMP"), Some("before".into()));
+
+    // ビルトインや関数の実行中は上書きして、終わったら元に戻す
+    let env = [
+        ("ASARI_TEST_TEMP".to_string(), None),
+        ("ASARI_TEST_TEMP_NEW".to_string(), Some("a".to_string())),
+        ("ASARI_TEST_TEMP_NEW".to_string(), Some("b".to_string())),
+    ];
+    shell.with_env(&env, |shell| {
+        assert_eq!(shell.env_var("ASARI_TEST_TEMP"), None);
+        assert_eq!(shell.env_var("ASARI_TEST_TEMP_NEW"), Some("b".into()));
+    });
+    assert_eq!(shell.env_var("ASARI_TEST_TEMP"), Some("before".into()));
+    assert_eq!(shell.env_var("ASARI_TEST_TEMP_NEW"), None);
+    // パイプの途中で呼び出した関数でも外には残らない
+    #[cfg(unix)]
+    {
+        let temp = TempDir::new("temp_env");
+        let out = temp.path().join("out.txt");
+        run(&mut shell, "fn show() { printenv ASARI_TEST_TEMP }");
+        let piped = run_with_timeout(
+            shell.clone(),
+            &format!(
+                "$ASARI_TEST_TEMP := during show | {{ sleep 0.1; show }} > {}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(out).unwrap(), "before\n");
+        assert_eq!(piped.env_var("ASARI_TEST_TEMP"), Some("before".into()));
+    }
+
+    // 環境変数は文字列のみ
+    let command = parse_command("$ASARI_TEST_TEMP := $? ls");
+    assert_eq!(
+        shell.eval_temp_env(&command.env),
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: "$ASARI_TEST_TEMP".to_string(),
+                expected: Type::String,
+                found: Type::Int
+            },
+            span: 20..22
+        })
+    );
+}
+
+#[test]
+fn expr_test() {
+    let mut shell = Shell::new();
+
+    // 型付きのリテラル
+    assert_eq!(eval(&shell, "echo (42)"), Ok(Value::Int(42)));
+    assert_eq!(eval(&shell, "echo (4.2)"), Ok(Value::Float(4.2)));
+    assert_eq!(eval(&shell, "echo (true)"), Ok(Value::Bool(true)));
+    assert_eq!(
+        eval(&shell, "echo ('hello')"),
+        Ok(Value::String("hello".to_string()))
+    );
+    assert_eq!(
+        expand(&shell, "echo (42) (0.5)"),
+        Ok(vec!["42".into(), "0.5".into()])
+    );
+
+    // 算術演算
+    assert_eq!(eval(&shell, "echo (1 + 2 * 3)"), Ok(Value::Int(7)));
+    assert_eq!(eval(&shell, "echo ((1 + 2) * 3)"), Ok(Value::Int(9)));
+    assert_eq!(eval(&shell, "echo (-7 / 2)"), Ok(Value::Int(-3)));
+    assert_eq!(eval(&shell, "echo (-7 % 2)"), Ok(Value::Int(-1)));
+    assert_eq!(eval(&shell, "echo (1.5 * 2.0)"), Ok(Value::Float(3.0)));
+    assert_eq!(
+        eval(&shell, "echo ('ab' + \"cd\")"),
+        Ok(Value::String("abcd".to_string()))
+    );
+
+    // 比較と論理演算
+    assert_eq!(eval(&shell, "echo (1 + 1 == 2)"), Ok(Value::Bool(true)));
+    assert_eq!(eval(&shell, "echo ('a' != 'a')"), Ok(Value::Bool(false)));
+    assert_eq!(eval(&shell, "echo (1.5 <= 1.5)"), Ok(Value::Bool(true)));
+    assert_eq!(eval(&shell, "echo ('b' > 'a')"), Ok(Value::Bool(true)));
+    assert_eq!(
+        eval(&shell, "echo (1 < 2 && !(2 < 1))"),
+        Ok(Value::Bool(true))
+    );
+    // 短絡評価されるので右辺のエラーは起きない
+    assert_eq!(
+        eval(&shell, "echo (true || %undefined)"),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        eval(&shell, "echo (false && %undefined)"),
+        Ok(Value::Bool(false))
+    );
+
+    // 変数と後置演算子
+    run(&mut shell, "%n = (10)");
+    assert_eq!(eval(&shell, "echo (%n % 3)"), Ok(Value::Int(1)));
+    run(&mut shell, "$ASARI_TEST_EXPR = none");
+    assert_eq!(
+        eval(&shell, "echo ($ASARI_TEST_EXPR ^ 'none'@)"),
+        Ok(Value::Int(4))
+    );
+    assert_eq!(
+        eval(&shell, "echo (!$ASARI_TEST_EXPR?)"),
+        Ok(Value::Bool(true))
+    );
+
+    // 暗黙の型変換はしない
+    assert_eq!(
+        eval(&shell, "echo (1 + 1.5)"),
+        error(
+            EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::Int,
+                right: Type::Float
+            },
+            6..13
+        )
+    );
+    assert_eq!(
+        eval(&shell, "echo (1 && true)"),
+        error(
+            EvalErrorKind::TypeMismatch {
+                expected: "bool",
+                found: Type::Int
+            },
+            6..7
+        )
+    );
+    assert_eq!(
+        eval(&shell, "echo (-'a')"),
+        error(
+            EvalErrorKind::InvalidUnaryOperand {
+                op: UnaryOp::Neg,
+                operand: Type::String
+            },
+            6..10
+        )
+    );
+
+    // 整数演算のエラー
+    assert_eq!(
+        eval(&shell, "echo (1 % 0)"),
+        error(EvalErrorKind::DivisionByZero, 6..11)
+    );
+    assert_eq!(
+        eval(&shell, "echo (9223372036854775807 + 1)"),
+        error(EvalErrorKind::Overflow, 6..29)
+    );
+}
+
+#[test]
+fn check_test() {
+    let mut shell = Shell::new();
+
+    // 整数と小数は混ぜられない
+    assert_eq!(
+        check(&shell, "echo (1 + 1.5)"),
+        error(
+            EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::Int,
+                right: Type::Float
+            },
+            6..13
+        )
+    );
+    assert_eq!(check(&shell, "echo ((1 + 2) * 3 == 9 && true)"), Ok(()));
+    assert_eq!(
+        check(&shell, "echo (!1)"),
+        error(
+            EvalErrorKind::InvalidUnaryOperand {
+                op: UnaryOp::Not,
+                operand: Type::Int
+            },
+            6..8
+        )
+    );
+    assert_eq!(
+        check(&shell, "echo (1 < 2 || 'a')"),
+        error(
+            EvalErrorKind::TypeMismatch {
+                expected: "bool",
+                found: Type::String
+            },
+            15..18
+        )
+    );
+
+    // 演算子とデフォルト値
+    assert_eq!(check(&shell, "echo ($HOME!@ + 1) ($! ^ (0))"), Ok(()));
+    assert_eq!(
+        check(&shell, "echo $HOME@"),
+        error(
+            EvalErrorKind::NoLength(Type::Option(Box::new(Type::String))),
+            5..10
+        )
+    );
+    assert_eq!(
+        check(&shell, "echo $! ^ none"),
+        error(
+            EvalErrorKind::TypeMismatch {
+                expected: "int",
+                found: Type::String
+            },
+            10..14
+        )
+    );
+
+    // 同じ行の代入を追跡する
+    assert_eq!(check(&shell, "%a = (1); echo (%a + 1)"), Ok(()));
+    assert_eq!(
+        check(&shell, "%a = (1); %a = x"),
+        error(
+            EvalErrorKind::VarTypeMismatch {
+                name: "%a".to_string(),
+                expected: Type::Int,
+                found: Type::String
+            },
+            15..16
+        )
+    );
+    assert_eq!(
+        check(&shell, "echo %a"),
+        error(EvalErrorKind::UndefinedVariable("a".to_string()), 5..7)
+    );
+    assert_eq!(
+        check(&shell, "%o: option<int> = (1); echo (%o! + 1)"),
+        Ok(())
+    );
+    assert_eq!(
+        check(&shell, "%a = x; %a = none; echo %a"),
+        error(EvalErrorKind::UndefinedVariable("a".to_string()), 24..26)
+    );
+    assert_eq!(
+        check(&shell, "$A := (1) ls"),
+        error(
+            EvalErrorKind::VarTypeMismatch {
+                name: "$A".to_string(),
+                expected: Type::String,
+                found: Type::Int
+            },
+            6..9
+        )
+    );
+
+    // 型エラーがあれば行全体を実行しない
+    run(&mut shell, "%a = (1); %b = (%a + 0.5)");
+    assert_eq!(shell.last_status(), 1);
+    assert_eq!(shell.vars.get("a"), None);
+
+    // 実行済みの変数の型を使う
+    run(&mut shell, "%a = (1)");
+    assert_eq!(
+        check(&shell, "echo (%a + 'x')"),
+        error(
+            EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::Int,
+                right: Type::String
+            },
+            6..14
+        )
+    );
+}
+
+#[test]
+fn as_test() {
+    let shell = Shell::new();
+    let failed = |value: &str, to: Type, span: Span| {
+        Err(EvalError {
+            kind: EvalErrorKind::CastFailed {
+                value: value.to_string(),
+                to,
+            },
+            span,
+        })
+    };
+
+    // 文字列への変換
+    assert_eq!(
+        eval(&shell, "echo (42 as string)"),
+        Ok(Value::String("42".to_string()))
+    );
+    assert_eq!(
+        eval(&shell, "echo (2.5 as string)"),
+        Ok(Value::String("2.5".to_string()))
+    );
+    assert_eq!(
+        eval(&shell, "echo (true as string)"),
+        Ok(Value::String("true".to_string()))
+    );
+    if let Some(home) =
+        dirs::home_dir().and_then(|home| home.to_str().map(str::to_string))
+    {
+        assert_eq!(
+            eval(&shell, "echo (p\"~/file\" as string)"),
+            Ok(Value::String(format!("{home}/file")))
+        );
+    }
+
+    // 数値の変換
+    assert_eq!(eval(&shell, "echo (4.2 as int)"), Ok(Value::Int(4)));
+    assert_eq!(eval(&shell, "echo (-4.8 as int)"), Ok(Value::Int(-4)));
+    assert_eq!(eval(&shell, "echo (3 as float)"), Ok(Value::Float(3.0)));
+    assert_eq!(eval(&shell, "echo (true as int)"), Ok(Value::Int(1)));
+    assert_eq!(
+        eval(&shell, "echo ((1 as float) + 1.5)"),
+        Ok(Value::Float(2.5))
+    );
+    assert_eq!(eval(&shell, "echo (1 + (1.5 as int))"), Ok(Value::Int(2)));
+    assert_eq!(
+        eval(&shell, "echo ('1e300' as float as int)"),
+        failed(&1e300.to_string(), Type::Int, 6..29)
+    );
+    assert_eq!(
+        eval(&shell, "echo ((0.0 / 0.0) as int)"),
+        failed("NaN", Type::Int, 6..24)
+    );
+
+    // 文字列からの変換
+    assert_eq!(eval(&shell, "echo ('42' as int)"), Ok(Value::Int(42)));
+    assert_eq!(
+        eval(&shell, "echo ('-0.5' as float)"),
+        Ok(Value::Float(-0.5))
+    );
+    assert_eq!(
+        eval(&shell, "echo ('false' as bool)"),
+        Ok(Value::Bool(false))
+    );
+    assert_eq!(
+        eval(&shell, "echo ('a b' as path)"),
+        Ok(Value::Path("a b".into()))
+    );
+    assert_eq!(
+        eval(&shell, "echo ('4.2' as int)"),
+        failed("4.2", Type::Int, 6..18)
+    );
+    assert_eq!(
+        eval(&shell, "echo ('abc' as float)"),
+        failed("abc", Type::Float, 6..20)
+    );
+    assert_eq!(
+        eval(&shell, "echo ('99999999999999999999' as int)"),
+        failed("99999999999999999999", Type::Int, 6..35)
+    );
+    assert_eq!(
+        eval(&shell, "echo ('yes' as bool)"),
+        failed("yes", Type::Bool, 6..19)
+    );
+
+    // 変換できない型の組み合わせは実行前に検出する
+    let command =
+        crate::parse::parse_shell_command("echo (1.5 as bool)").unwrap();
+    assert_eq!(
+        shell.check(&command),
+        Err(EvalError {
+            kind: EvalErrorKind::InvalidCast {
+                from: Type::Float,
+                to: Type::Bool
+            },
+            span: 6..17
+        })
+    );
+    assert_eq!(
+        eval(&shell, "echo ($HOME as string)"),
+        Err(EvalError {
+            kind: EvalErrorKind::InvalidCast {
+                from: Type::Option(Box::new(Type::String)),
+                to: Type::String
+            },
+            span: 6..21
+        })
+    );
+}
+
+#[test]
+fn array_test() {
+    let mut shell = Shell::new();
+    let ints = |ints: &[i64]| {
+        Value::Array(ints.iter().copied().map(Value::Int).collect())
+    };
+
+    run(&mut shell, "%files = ['a.txt', 'b c.txt']");
+    assert_eq!(
+        shell.vars.get("files").map(|var| &var.ty),
+        Some(&Type::Array(Box::new(Type::String)))
+    );
+    // 配列は複数の引数に展開される
+    assert_eq!(
+        expand(&shell, "rm %files -f"),
+        Ok(vec!["a.txt".into(), "b c.txt".into(), "-f".into()])
+    );
+
+    // インデックスはoptionを返し、負の数は末尾から数える
+    assert_eq!(
+        eval(&shell, "echo %files[1]"),
+        Ok(Value::some(Value::String("b c.txt".to_string())))
+    );
+    assert_eq!(
+        eval(&shell, "echo %files[-2]!"),
+        Ok(Value::String("a.txt".to_string()))
+    );
+    assert_eq!(eval(&shell, "echo %files[2]"), Ok(Value::none()));
+    assert_eq!(eval(&shell, "echo %files[-3]"), Ok(Value::none()));
+
+    // 要素の更新
+    run(&mut shell, "%nums = [1, 2, 3]; %nums[-1] = (30)");
+    assert_eq!(
+        shell.vars.get("nums").map(|var| &var.value),
+        Some(&ints(&[1, 2, 30]))
+    );
+    let command = crate::parse::parse_shell_command("%nums[3] = (4)").unwrap();
+    let Statement::Assign(assign) = &command.commands[0].0
+    else {
+        unreachable!()
+    };
+    assert_eq!(
+        shell.assign(assign),
+        Err(EvalError {
+            kind: EvalErrorKind::IndexOutOfRange { index: 3, len: 3 },
+            span: 6..7
+        })
+    );
+    assert_eq!(
+        check(&shell, "%nums[0] = x"),
+        error(
+            EvalErrorKind::VarTypeMismatch {
+                name: "%nums[0]".to_string(),
+                expected: Type::Int,
+                found: Type::String
+            },
+            11..12
+        )
+    );
+
+    // 末尾への追加
+    run(&mut shell, "%nums = (%nums + 4)");
+    assert_eq!(
+        shell.vars.get("nums").map(|var| &var.value),
+        Some(&ints(&[1, 2, 30, 4]))
+    );
+    assert_eq!(
+        check(&shell, "echo (%nums + 1.5)"),
+        error(
+            EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::Array(Box::new(Type::Int)),
+                right: Type::Float
+            },
+            6..17
+        )
+    );
+
+    // 要素の型は揃える
+    assert_eq!(
+        check(&shell, "echo ([1, 'a'])"),
+        error(
+            EvalErrorKind::TypeMismatch {
+                expected: "int",
+                found: Type::String
+            },
+            10..13
+        )
+    );
+    // 空の配列には型注釈が必要
+    assert_eq!(
+        check(&shell, "%empty = []"),
+        error(EvalErrorKind::UntypedEmptyArray, 9..11)
+    );
+    // noneも同じ
+    assert_eq!(
+        check(&shell, "%undeclared = none"),
+        error(EvalErrorKind::UntypedNone, 14..18)
+    );
+    run(&mut shell, "%empty: array<int> = []; %empty = (%empty + 1)");
+    assert_eq!(
+        shell.vars.get("empty").map(|var| &var.value),
+        Some(&ints(&[1]))
+    );
+}
+
+#[test]
+fn len_test() {
+    let mut shell = Shell::new();
+
+    assert_eq!(eval(&shell, "echo \"hello\"@"), Ok(Value::Int(5)));
+    assert_eq!(eval(&shell, "echo 'あいう'@"), Ok(Value::Int(3)));
+    assert_eq!(eval(&shell, "echo ([1, 2, 3]@)"), Ok(Value::Int(3)));
+    assert_eq!(eval(&shell, "echo (\"ab\" + \"cd\")@"), Ok(Value::Int(4)));
+    run(&mut shell, "%name = asari; %nums = [1, 2]");
+    assert_eq!(eval(&shell, "echo %name@"), Ok(Value::Int(5)));
+    assert_eq!(eval(&shell, "echo (%nums@ * 10)"), Ok(Value::Int(20)));
+    assert_eq!(
+        eval(&shell, "echo $@@"),
+        Ok(Value::Int(shell.shell_name.chars().count() as i64))
+    );
+    // パスは展開後の文字数
+    if let Some(home) =
+        dirs::home_dir().and_then(|home| home.to_str().map(str::to_string))
+    {
+        assert_eq!(
+            eval(&shell, "echo p\"~/file\"@"),
+            Ok(Value::Int(home.chars().count() as i64 + 5))
+        );
+    }
+
+    // 長さのない型
+    let command = crate::parse::parse_shell_command("echo (1)@").unwrap();
+    assert_eq!(
+        shell.check(&command),
+        Err(EvalError {
+            kind: EvalErrorKind::NoLength(Type::Int),
+            span: 5..8
+        })
+    );
+}
+
+#[test]
+fn option_test() {
+    let mut shell = Shell::new();
+
+    assert_eq!(
+        eval(&shell, "echo (some(42))"),
+        Ok(Value::some(Value::Int(42)))
+    );
+    assert_eq!(eval(&shell, "echo (none)"), Ok(Value::none()));
+
+    run(&mut shell, "%opt = some(42)");
+    assert_eq!(
+        shell.vars.get("opt"),
+        Some(&Var {
+            ty: Type::Option(Box::new(Type::Int)),
+            value: Value::some(Value::Int(42))
+        })
+    );
+    assert_eq!(eval(&shell, "echo %opt?"), Ok(Value::Bool(true)));
+    assert_eq!(eval(&shell, "echo (%opt! + 1)"), Ok(Value::Int(43)));
+    assert_eq!(
+        eval(&shell, "echo (%opt == some(42))"),
+        Ok(Value::Bool(true))
+    );
+
+    // noneを代入しても型は残る
+    run(&mut shell, "%opt = none");
+    assert_eq!(eval(&shell, "echo %opt?"), Ok(Value::Bool(false)));
+    assert_eq!(eval(&shell, "echo %opt ^ (7)"), Ok(Value::Int(7)));
+    assert_eq!(eval(&shell, "echo (%opt == none)"), Ok(Value::Bool(true)));
+    // unwrapの失敗は変数名と位置を報告する
+    assert_eq!(
+        eval(&shell, "echo %opt!"),
+        Err(EvalError {
+            kind: EvalErrorKind::UnwrapNone("%opt".to_string()),
+            span: 5..9
+        })
+    );
+    assert_eq!(
+        check(&shell, "%opt = some('a')"),
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: "%opt".to_string(),
+                expected: Type::Option(Box::new(Type::Int)),
+                found: Type::Option(Box::new(Type::String))
+            },
+            span: 7..16
+        })
+    );
+    assert_eq!(check(&shell, "echo (some(1) ^ (2))"), Ok(()));
+}
+
+#[test]
+fn script_test() {
+    let mut shell = Shell::new();
+
+    shell.set_script(
+        "script.asr".to_string(),
+        vec!["a".to_string(), "b c".to_string()],
+    );
+    assert_eq!(
+        eval(&shell, "echo $@"),
+        Ok(Value::String("script.asr".to_string()))
+    );
+    assert_eq!(
+        shell.vars.get("args"),
+        Some(&Var {
+            ty: Type::Array(Box::new(Type::String)),
+            value: Value::Array(vec![
+                Value::String("a".to_string()),
+                Value::String("b c".to_string())
+            ])
+        })
+    );
+    assert_eq!(
+        expand(&shell, "echo %args"),
+        Ok(vec!["a".into(), "b c".into()])
+    );
+
+    // 引数がなくても型は決まっている
+    shell.set_script("script.asr".to_string(), vec![]);
+    run(&mut shell, "%args = (%args + 'x')");
+    assert_eq!(
+        eval(&shell, "echo %args[0]!"),
+        Ok(Value::String("x".to_string()))
+    );
+}
+
+#[test]
+fn if_test() {
+    let mut shell = Shell::new();
+    let result =
+        |shell: &Shell| shell.vars.get("r").map(|var| var.value.clone());
+
+    run(&mut shell, "%x = (3)");
+    run(&mut shell, "if (%x > 2) { %r = big } else { %r = small }");
+    assert_eq!(result(&shell), Some(Value::String("big".to_string())));
+    run(
+        &mut shell,
+        "if (%x > 5) {\n  %r = big\n} else if (%x > 1) {\n  %r = middle\n} else {\n  %r = small\n}",
+    );
+    assert_eq!(result(&shell), Some(Value::String("middle".to_string())));
+
+    // コマンドは終了ステータスが0なら真
+    run(&mut shell, "if false { %r = false } else { %r = true }");
+    assert_eq!(result(&shell), Some(Value::String("true".to_string())));
+    // ブロックの最後の終了ステータスがif全体の終了ステータスになる
+    run(&mut shell, "if true { false }");
+    assert_eq!(shell.last_status(), 1);
+    run(&mut shell, "if false { false }");
+    assert_eq!(shell.last_status(), 0);
+    // パイプの段になれば入出力がつながる
+    #[cfg(unix)]
+    {
+        let temp = TempDir::new("if");
+        let out = temp.path().join("out.txt");
+        run(
+            &mut shell,
+            &format!(
+                "if (%x > 2) {{ echo yes }} | tr a-z A-Z > {}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "YES\n");
+        run(
+            &mut shell,
+            &format!("echo piped | if true {{ cat > {} }}", out.display()),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "piped\n");
+    }
+
+    // 分岐で型が異なる変数はunknown型として検査を続ける
+    assert_eq!(
+        check(
+            &shell,
+            "if true { %y = (1) } else { %y = a }; echo (%y + 1)"
+        ),
+        Ok(())
+    );
+    assert_eq!(check(&shell, "if true { %z = (1) }; echo (%z + 1)"), Ok(()));
+    assert_eq!(
+        check(&shell, "if (%x) { echo a }"),
+        Err(EvalError {
+            kind: EvalErrorKind::TypeMismatch {
+                expected: "bool",
+                found: Type::Int
+            },
+            span: 3..7
+        })
+    );
+    assert_eq!(
+        check(&shell, "if true { echo %undefined }"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("undefined".to_string()),
+            span: 15..25
+        })
+    );
+    // パイプの途中の段はサブシェルになる
+    assert_eq!(
+        check(&shell, "if true { %w = (1) } | cat; echo %w"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("w".to_string()),
+            span: 33..35
+        })
+    );
+}
+
+#[test]
+fn loop_test() {
+    let mut shell = Shell::new();
+
+    run(&mut shell, "%sum = (0)");
+    run(&mut shell, "for %i in 1..=4 { %sum = (%sum + %i) }");
+    assert_eq!(value(&shell, "sum"), Some(Value::Int(10)));
+    // ループ変数はループの後で元に戻る
+    assert_eq!(value(&shell, "i"), None);
+    run(&mut shell, "%x = outside");
+    run(&mut shell, "for %x in [\"a\", \"b\"] { %last = %x }");
+    assert_eq!(value(&shell, "last"), Some(Value::String("b".to_string())));
+    assert_eq!(
+        value(&shell, "x"),
+        Some(Value::String("outside".to_string()))
+    );
+
+    run(&mut shell, "%n = (0)");
+    run(&mut shell, "while (%n < 5) {\n  %n = (%n + 1)\n}");
+    assert_eq!(value(&shell, "n"), Some(Value::Int(5)));
+
+    // breakとcontinue
+    run(&mut shell, "%sum = (0)");
+    run(
+        &mut shell,
+        "for %i in 0..10 {\n  if (%i % 2 == 1) { continue }\n  if (%i > 6) { break }\n  %sum = (%sum + %i)\n}",
+    );
+    assert_eq!(value(&shell, "sum"), Some(Value::Int(12)));
+    // ラベルで外側のループを抜ける
+    run(&mut shell, "%count = (0)");
+    run(
+        &mut shell,
+        "'outer: for %i in 0..3 {\n  for %j in 0..3 {\n    if (%j == 2) { continue 'outer }\n    if (%i == 2) { break 'outer }\n    %count = (%count + 1)\n  }\n}",
+    );
+    assert_eq!(value(&shell, "count"), Some(Value::Int(4)));
+    // パイプの段になれば入出力がつながる
+    #[cfg(unix)]
+    {
+        let temp = TempDir::new("loop");
+        let out = temp.path().join("out.txt");
+        run(
+            &mut shell,
+            &format!(
+                "for %x in [3, 1, 2] {{ echo %x }} | sort > {}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "1\n2\n3\n");
+        run(
+            &mut shell,
+            &format!(
+                "printf 'a\\nb\\n' | while true {{ cat > {}; break }}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "a\nb\n");
+    }
+
+    // 型検査
+    assert_eq!(check(&shell, "for %i in 0..3 { echo (%i + 1) }"), Ok(()));
+    assert_eq!(
+        check(&shell, "for %s in [\"a\"] { echo (%s + 1) }"),
+        Err(EvalError {
+            kind: EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::String,
+                right: Type::Int
+            },
+            span: 24..30
+        })
+    );
+    assert_eq!(
+        check(&shell, "for %i in 0..3 { }; echo %i"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("i".to_string()),
+            span: 25..27
+        })
+    );
+    assert_eq!(
+        check(&shell, "for %c in %count { }"),
+        Err(EvalError {
+            kind: EvalErrorKind::TypeMismatch {
+                expected: "array",
+                found: Type::Int
+            },
+            span: 10..16
+        })
+    );
+    assert_eq!(
+        check(&shell, "if true { continue }"),
+        Err(EvalError {
+            kind: EvalErrorKind::OutsideLoop("continue"),
+            span: 10..18
+        })
+    );
+    assert_eq!(
+        check(&shell, "'a: while true { for %i in 0..1 { break 'b } }"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedLabel("b".to_string()),
+            span: 34..42
+        })
+    );
+    // パイプの途中の段から外のループは抜けられない
+    assert_eq!(
+        check(
+            &shell,
+            "'a: for %i in 0..1 { for %j in 0..1 { break 'a } | cat }"
+        ),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedLabel("a".to_string()),
+            span: 38..46
+        })
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn function_test() {
+    let temp = TempDir::new("function");
+    let out = temp.path().join("out.txt");
+    let out = out.to_str().unwrap();
+    let mut shell = Shell::new();
+    // 実行時のエラーを表示せずに返す
+    let eval_error = |shell: &mut Shell, line: &str| {
+        let command = crate::parse::parse_shell_command(line).unwrap();
+        let pipeline = vec![&command.commands[0].0];
+        match shell.execute_pipeline(&pipeline, line.into()) {
+            Err(Error::Eval(e)) => e,
+            result => panic!("{result:?}"),
+        }
+    };
+
+    // 引数は宣言された型に変換される
+    run(
+        &mut shell,
+        "fn add(%a: int, %b: int = 10) {\n  %sum = (%a + %b)\n  return %sum\n}",
+    );
+    run(&mut shell, "add 1 2");
+    assert_eq!(shell.last_status(), 3);
+    run(&mut shell, "add 5");
+    assert_eq!(shell.last_status(), 15);
+    // 関数の中の変数は呼び出しの後で元に戻る
+    assert_eq!(value(&shell, "sum"), None);
+    assert_eq!(value(&shell, "a"), None);
+    run(&mut shell, "%a = outside; add 1 1");
+    assert_eq!(value(&shell, "a"), Some(Value::String("outside".into())));
+    // 呼び出し元の変数と同じ名前への代入は関数の中だけ
+    run(&mut shell, "%n = 1; fn set() { %n = 2 }; set");
+    assert_eq!(value(&shell, "n"), Some(Value::String("1".into())));
+    // デフォルト値では前の引数を使える
+    run(
+        &mut shell,
+        "fn twice(%a: int, %b: int = %a) { return (%a + %b) }",
+    );
+    run(&mut shell, "twice 2");
+    assert_eq!(shell.last_status(), 4);
+
+    // option型の引数と残りの引数を受け取る配列型の引数
+    run(
+        &mut shell,
+        "fn args(%first: option<string>, %rest: array<int>) { echo %first %rest@ }",
+    );
+    run(&mut shell, &format!("args > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "0\n");
+    run(&mut shell, &format!("args a 1 2 > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a 2\n");
+
+    // 出力はリダイレクトやパイプにつながる
+    run(&mut shell, "fn greet(%name: string) { echo hello %name }");
+    run(&mut shell, &format!("greet world > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "hello world\n");
+    run(&mut shell, &format!("greet a | tr a-z A-Z > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "HELLO A\n");
+    run(&mut shell, "fn upper() { tr a-z A-Z }");
+    run(&mut shell, &format!("echo b | upper > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "B\n");
+    // 終わらない出力も後の段が読み終われば止まる
+    run(&mut shell, "fn endless() { yes }");
+    let mut shell =
+        run_with_timeout(shell, &format!("endless | head -n 1 > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "y\n");
+
+    // 数値とbool以外のreturnの値は出力になる
+    run(&mut shell, "fn text() { return \"a b\" }");
+    run(&mut shell, &format!("text > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a b\n");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "fn positive(%x: int) { return (%x > 0) }");
+    run(&mut shell, "positive 1");
+    assert_eq!(shell.last_status(), 0);
+    run(&mut shell, "positive (-1)");
+    assert_eq!(shell.last_status(), 1);
+    // returnはループの中からでも関数を抜ける
+    run(
+        &mut shell,
+        "fn find() {\n  for %i in 0..10 { if (%i == 4) { return %i } }\n  return 100\n}",
+    );
+    run(&mut shell, "find");
+    assert_eq!(shell.last_status(), 4);
+
+    // 引数のエラー
+    assert_eq!(
+        eval_error(&mut shell, "add"),
+        EvalError {
+            kind: EvalErrorKind::MissingArgument("%a".to_string()),
+            span: 0..3
+        }
+    );
+    assert_eq!(
+        eval_error(&mut shell, "add 1 x"),
+        EvalError {
+            kind: EvalErrorKind::CastFailed {
+                value: "x".to_string(),
+                to: Type::Int
+            },
+            span: 6..7
+        }
+    );
+    assert_eq!(
+        eval_error(&mut shell, "args a 1 x"),
+        EvalError {
+            kind: EvalErrorKind::CastFailed {
+                value: "x".to_string(),
+                to: Type::Int
+            },
+            span: 9..10
+        }
+    );
+    assert_eq!(
+        eval_error(&mut shell, "greet a b"),
+        EvalError {
+            kind: EvalErrorKind::TooManyArguments("greet".to_string()),
+            span: 8..9
+        }
+    );
+    // 深すぎる再帰はスタックがあふれる前にエラーになる
+    let (status, depth) = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let mut shell = Shell::new();
+            run(&mut shell, "fn deep(%n: int) { deep (%n + 1) }");
+            run(&mut shell, "deep 0");
+            (shell.last_status(), shell.call_depth)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!((status, depth), (1, 0));
+    shell.call_depth = function::MAX_CALL_DEPTH;
+    assert_eq!(
+        eval_error(&mut shell, "add 1 2"),
+        EvalError {
+            kind: EvalErrorKind::RecursionLimit("add".to_string()),
+            span: 0..3
+        }
+    );
+    shell.call_depth = 0;
+
+    // 型検査
+    assert_eq!(check(&shell, "fn f(%x: int) { echo (%x + 1) }"), Ok(()));
+    assert_eq!(
+        check(&shell, "fn f(%x: string) { echo (%x + 1) }"),
+        Err(EvalError {
+            kind: EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::String,
+                right: Type::Int
+            },
+            span: 25..31
+        })
+    );
+    assert_eq!(
+        check(&shell, "fn f(%x: int = true) { }"),
+        Err(EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: "%x".to_string(),
+                expected: Type::Int,
+                found: Type::Bool
+            },
+            span: 15..19
+        })
+    );
+    assert_eq!(check(&shell, "fn f(%x: array<int> = []) { }"), Ok(()));
+    // 呼び出し元の変数は関数の中から見えない
+    assert_eq!(
+        check(&shell, "%count = (0); fn inc() { %count = (%count + 1) }"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("count".to_string()),
+            span: 35..41
+        })
+    );
+    assert_eq!(
+        check(&shell, "fn show() { echo %x }; %x = hi; show"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("x".to_string()),
+            span: 17..19
+        })
+    );
+    // 関数の中の変数は外から見えない
+    assert_eq!(
+        check(&shell, "fn f() { %y = 1 }; echo %y"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("y".to_string()),
+            span: 24..26
+        })
+    );
+    assert_eq!(
+        check(&shell, "return 1"),
+        Err(EvalError {
+            kind: EvalErrorKind::ReturnOutsideFunction,
+            span: 0..8
+        })
+    );
+    assert_eq!(
+        check(&shell, "for %i in 0..1 { fn f() { break } }"),
+        Err(EvalError {
+            kind: EvalErrorKind::OutsideLoop("break"),
+            span: 26..31
+        })
+    );
+}
+
+#[test]
+fn match_test() {
+    let mut shell = Shell::new();
+    let string = |s: &str| Some(Value::String(s.to_string()));
+
+    // 文字列とグロブ
+    let kind = |shell: &mut Shell, file: &str| {
+        run(
+            shell,
+            &format!(
+                "match {file} {{ \"*\" => {{ %kind = star }}; *.rs | *.toml => {{ %kind = rust }}; _ => {{ %kind = other }} }}"
+            ),
+        );
+        value(shell, "kind")
+    };
+    assert_eq!(kind(&mut shell, "main.rs"), string("rust"));
+    assert_eq!(kind(&mut shell, "Cargo.toml"), string("rust"));
+    assert_eq!(kind(&mut shell, "'*'"), string("star"));
+    assert_eq!(kind(&mut shell, "README"), string("other"));
+    // パスの値も文字列として一致させる
+    assert_eq!(kind(&mut shell, "p\"src/main.rs\""), string("rust"));
+
+    // 整数とbool
+    run(&mut shell, "%n = (2)");
+    run(
+        &mut shell,
+        "match %n { 1 | 2 => { %small = (true) }; _ => { %small = (false) } }",
+    );
+    assert_eq!(value(&shell, "small"), Some(Value::Bool(true)));
+    run(
+        &mut shell,
+        "match (%n > 5) { true => { %big = (true) }; false => { %big = (false) } }",
+    );
+    assert_eq!(value(&shell, "big"), Some(Value::Bool(false)));
+    // どの腕にも一致しなければ何もしない
+    run(&mut shell, "sh -c 'exit 3'; match %n { 10 => echo ten }");
+    assert_eq!(shell.last_status(), 0);
+    // パイプの段になれば入出力がつながる
+    #[cfg(unix)]
+    {
+        let temp = TempDir::new("match");
+        let out = temp.path().join("out.txt");
+        run(
+            &mut shell,
+            &format!(
+                "match %n {{ 2 => echo two; _ => echo other }} | tr a-z A-Z > {}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "TWO\n");
+        run(
+            &mut shell,
+            &format!(
+                "echo piped | match %n {{ _ => cat > {} }}",
+                out.display()
+            ),
+        );
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "piped\n");
+    }
+    // パイプの途中の段での代入は外に残らない
+    assert_eq!(
+        check(&shell, "match %n { _ => { %m = (1) } } | cat; echo %m"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("m".to_string()),
+            span: 43..45
+        })
+    );
+
+    // optionの分解
+    run(&mut shell, "%o: option<int> = some(4)");
+    run(
+        &mut shell,
+        "match %o { some(%v) => { %got = (%v * 2) }; none => { %got = (0) } }",
+    );
+    assert_eq!(value(&shell, "got"), Some(Value::Int(8)));
+    // 束縛した変数は腕の後で元に戻る
+    assert_eq!(value(&shell, "v"), None);
+    run(&mut shell, "%o = none");
+    run(
+        &mut shell,
+        "match %o { some(%v) => { %got = %v }; none => { %got = (0) } }",
+    );
+    assert_eq!(value(&shell, "got"), Some(Value::Int(0)));
+    run(
+        &mut shell,
+        "match $ASARI_TEST_MATCH_MISSING { some(%v) => { %env = %v }; none => { %env = unset } }",
+    );
+    assert_eq!(value(&shell, "env"), string("unset"));
+
+    // 型検査
+    assert_eq!(
+        check(
+            &shell,
+            "match %o { some(%v) => echo (%v + 1); none => echo }"
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check(
+            &shell,
+            "match %o { some(%v) => echo (%v + \"a\"); none => echo }"
+        ),
+        Err(EvalError {
+            kind: EvalErrorKind::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                left: Type::Int,
+                right: Type::String
+            },
+            span: 29..37
+        })
+    );
+    assert_eq!(
+        check(&shell, "match %n { \"a\" => echo }"),
+        Err(EvalError {
+            kind: EvalErrorKind::TypeMismatch {
+                expected: "string",
+                found: Type::Int
+            },
+            span: 11..14
+        })
+    );
+    // boolとoptionはすべての値に一致する腕が必要
+    assert_eq!(
+        check(&shell, "match %small { true => echo }"),
+        Err(EvalError {
+            kind: EvalErrorKind::NonExhaustive("false".to_string()),
+            span: 6..12
+        })
+    );
+    assert_eq!(
+        check(&shell, "match %o { some(1) => echo; none => echo }"),
+        Err(EvalError {
+            kind: EvalErrorKind::NonExhaustive("some(_)".to_string()),
+            span: 6..8
+        })
+    );
+    assert_eq!(check(&shell, "match %n { 1 => echo }"), Ok(()));
+    assert_eq!(
+        check(&shell, "match %o { some(%v) | none => echo }"),
+        Err(EvalError {
+            kind: EvalErrorKind::InconsistentBinding("v".to_string()),
+            span: 22..26
+        })
+    );
+    // 腕によって型が変わる変数はunknown型になる
+    assert_eq!(
+        check(
+            &shell,
+            "match %n { 1 => { %m = (1) }; _ => { %m = a } }; echo (%m + 1)"
+        ),
+        Ok(())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn group_test() {
+    let temp = TempDir::new("group");
+    let out = temp.path().join("out.txt");
+    let out = out.to_str().unwrap();
+    let mut shell = Shell::new();
+
+    // リダイレクトとパイプはグループ全体に適用される
+    run(&mut shell, &format!("{{ echo a; echo b }} > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a\nb\n");
+    run(
+        &mut shell,
+        &format!("{{ echo a; echo b }} | tr a-z A-Z > {out}"),
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "A\nB\n");
+    run(
+        &mut shell,
+        &format!("printf 'x\\ny\\n' | {{ head -n 1; echo z }} > {out}"),
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "x\nz\n");
+    // 終わらない出力も後の段が読み終われば止まる
+    let mut shell =
+        run_with_timeout(shell, &format!("{{ yes }} | head -n 1 > {out}"));
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "y\n");
+    let mut shell = run_with_timeout(
+        shell,
+        &format!("{{ yes }} | {{ head -n 2 }} > {out}"),
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "y\ny\n");
+    // 終了ステータスは最後のコマンドのもの
+    run(&mut shell, "{ sh -c 'exit 3'; sh -c 'exit 4' }");
+    assert_eq!(shell.last_status(), 4);
+
+    // グループの中の代入は外に残る
+    run(&mut shell, "%x = (1); { %x = (2) }");
+    assert_eq!(value(&shell, "x"), Some(Value::Int(2)));
+    // サブシェルの変更は元に戻る
+    run(
+        &mut shell,
+        &format!(
+            "subshell {{ %x = (3); $ASARI_TEST_SUBSHELL = set; echo %x }} > {out}"
+        ),
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "3\n");
+    assert_eq!(value(&shell, "x"), Some(Value::Int(2)));
+    // パイプの途中のグループもサブシェルになる
+    run(&mut shell, "{ %x = (4) } | cat");
+    assert_eq!(value(&shell, "x"), Some(Value::Int(2)));
+    assert_eq!(shell.env_var("ASARI_TEST_SUBSHELL"), None);
+    run(&mut shell, "subshell { exit 5 }");
+    assert_eq!(shell.last_status(), 5);
+    // カレントディレクトリの変更も元に戻る
+    let cwd = shell.current_dir().to_path_buf();
+    let dir = temp.path().display();
+    run(&mut shell, &format!("subshell {{ cd {dir} }}"));
+    assert_eq!(shell.current_dir(), cwd);
+    run(&mut shell, &format!("{{ cd {dir} }} | cat"));
+    assert_eq!(shell.current_dir(), cwd);
+    // 同時に実行される段の変更は他の段に影響しない
+    run(&mut shell, &format!("cd {dir}; mkdir sub"));
+    let mut shell = run_with_timeout(
+        shell,
+        "{ cd sub; sleep 0.5; echo hi } | { sleep 0.1; cat > out.txt }",
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "hi\n");
+    assert!(!temp.path().join("sub/out.txt").exists());
+    let mut shell = run_with_timeout(
+        shell,
+        "{ $ASARI_TEST_STAGE = leaked; sleep 0.5 } \
+         | { sleep 0.1; printenv ASARI_TEST_STAGE > out.txt }",
+    );
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "");
+    // プロセスのカレントディレクトリは変更しない
+    assert_eq!(std::env::current_dir().unwrap(), cwd);
+
+    // 型検査
+    assert_eq!(check(&shell, "{ %y = (1) }; echo (%y + 1)"), Ok(()));
+    assert_eq!(
+        check(&shell, "subshell { %y = (1) }; echo %y"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("y".to_string()),
+            span: 28..30
+        })
+    );
+    assert_eq!(
+        check(&shell, "{ %z = (1) } | cat; echo %z"),
+        Err(EvalError {
+            kind: EvalErrorKind::UndefinedVariable("z".to_string()),
+            span: 25..27
+        })
+    );
+    assert_eq!(
+        check(&shell, "while true { subshell { break } }"),
+        Err(EvalError {
+            kind: EvalErrorKind::OutsideLoop("break"),
+            span: 24..29
+        })
+    );
+}
@@ -0,0 +1,271 @@
+use super::{EvalError, EvalErrorKind, Shell, eval::element_index};
+use crate::{
+    parse::{Assign, AssignTarget, AssignValue, Expr, Span, Spanned, TempEnv},
+    value::{Type, Value},
+};
+
+use std::{ffi::OsString, path::PathBuf, process::Command};
+
+type Result<T> = ::std::result::Result<T, EvalError>;
+
+/// 型を宣言したシェル変数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Var {
+    pub ty: Type,
+    pub value: Value,
+}
+impl From<Value> for Var {
+    /// 値から型を推論する
+    fn from(value: Value) -> Self {
+        Var {
+            ty: value.ty(),
+            value,
+        }
+    }
+}
+
+impl Shell {
+    /// 環境変数の値
+    ///
+    /// シェルで変更していなければプロセスの環境変数を読む
+    pub fn env_var(&self, name: &str) -> Option<OsString> {
+        match self.env.get(name) {
+            Some(value) => value.clone(),
+            None => std::env::var_os(name),
+        }
+    }
+    /// ホームディレクトリ
+    ///
+    /// `$HOME`が設定されていればそれを使う
+    pub(super) fn home_dir(&self) -> Option<PathBuf> {
+        match self.env.get("HOME") {
+            Some(home) => home.as_ref().map(PathBuf::from),
+            None => dirs::home_dir(),
+        }
+    }
+    /// シェルで変更した環境変数を外部コマンドに渡す
+    pub(super) fn pass_env(&self, command: &mut Command) {
+        for (name, value) in &self.env {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+    }
+    /// 環境変数を一時的に上書きして`f`を実行する
+    ///
+    /// 終わったら元の値に戻す
+    pub(super) fn with_env<T>(
+        &mut self,
+        env: &[(String, Option<String>)],
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let mut saved = Vec::new();
+        for (name, value) in env {
+            let value = value.as_ref().map(OsString::from);
+            saved.push((name.clone(), self.env.insert(name.clone(), value)));
+        }
+        let result = f(self);
+        // 同じ名前が複数回上書きされた場合に備えて逆順に戻す
+        for (name, value) in saved.into_iter().rev() {
+            match value {
+                Some(value) => self.env.insert(name, value),
+                None => self.env.remove(&name),
+            };
+        }
+        result
+    }
+    /// コマンドの前で設定された環境変数を評価する
+    pub(super) fn eval_temp_env(
+        &self,
+        env: &[TempEnv],
+    ) -> Result<Vec<(String, Option<String>)>> {
+        env.iter()
+            .map(|TempEnv { name, value }| {
+                let value = match value {
+                    Some(word) => Some((self.eval_word(word)?, word.span())),
+                    None => None,
+                };
+                Ok((name.clone(), env_value(name, value)?))
+            })
+            .collect()
+    }
+    /// 代入文を実行する
+    pub(super) fn assign(&mut self, assign: &Assign) -> Result<Value> {
+        // noneの場合は`none`の位置
+        let (value, span) = match &assign.value {
+            AssignValue::Word(word) => {
+                (Some(self.eval_word(word)?), word.span())
+            }
+            AssignValue::None(none) => (None, none.span()),
+        };
+        match assign.target.inner() {
+            AssignTarget::EnvVar(name) => {
+                let value = env_value(name, value.map(|value| (value, span)))?;
+                self.env.insert(name.clone(), value.map(OsString::from));
+            }
+            AssignTarget::ShellVar(name) => {
+                self.set_shell_var(name, assign.ty.as_ref(), value, span)?;
+            }
+            AssignTarget::Element(name, index) => {
+                self.set_element(&assign.target, name, index, value, span)?;
+            }
+        }
+        Ok(Value::Unit)
+    }
+    /// 配列の要素を更新する
+    fn set_element(
+        &mut self,
+        target: &Spanned<AssignTarget>,
+        name: &str,
+        index: &Spanned<Expr>,
+        value: Option<Value>,
+        span: Span,
+    ) -> Result<()> {
+        let i = self.eval_int(index)?;
+        let Some(var) = self.vars.get_mut(name)
+        else {
+            return Err(EvalError {
+                kind: EvalErrorKind::UndefinedVariable(name.to_string()),
+                span: target.span(),
+            });
+        };
+        let (Type::Array(ty), Value::Array(values)) = (&var.ty, &mut var.value)
+        else {
+            return Err(EvalError {
+                kind: EvalErrorKind::TypeMismatch {
+                    expected: "array",
+                    found: var.ty.clone(),
+                },
+                span: target.span(),
+            });
+        };
+        let value = value.unwrap_or_else(Value::none);
+        let value = coerce(ty, value).map_err(|value| EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: target.to_string(),
+                expected: (**ty).clone(),
+                found: value.ty(),
+            },
+            span,
+        })?;
+        let Some(i) = element_index(i, values.len())
+        else {
+            return Err(EvalError {
+                kind: EvalErrorKind::IndexOutOfRange {
+                    index: i,
+                    len: values.len(),
+                },
+                span: index.span(),
+            });
+        };
+        values[i] = value;
+        Ok(())
+    }
+    fn set_shell_var(
+        &mut self,
+        name: &str,
+        ty: Option<&Spanned<Type>>,
+        value: Option<Value>,
+        span: Span,
+    ) -> Result<()> {
+        // 型注釈があれば宣言し直し、なければ以前の型を引き継ぐ
+        let ty = match (ty, self.vars.get(name)) {
+            (Some(ty), _) => ty.inner().clone(),
+            (None, Some(var)) => var.ty.clone(),
+            (None, None) => {
+                // 型のない変数にはnoneを代入できない
+                let Some(value) = value
+                else {
+                    return Err(EvalError {
+                        kind: EvalErrorKind::UntypedNone,
+                        span,
+                    });
+                };
+                self.vars.insert(name.to_string(), Var::from(value));
+                return Ok(());
+            }
+        };
+        let Some(value) = value
+        else {
+            // option型以外の変数はnoneの代入で削除する
+            if matches!(ty, Type::Option(_)) {
+                self.vars.insert(
+                    name.to_string(),
+                    Var {
+                        ty,
+                        value: Value::none(),
+                    },
+                );
+            }
+            else {
+                self.vars.remove(name);
+            }
+            return Ok(());
+        };
+        let value = coerce(&ty, value).map_err(|value| EvalError {
+            kind: EvalErrorKind::VarTypeMismatch {
+                name: format!("%{name}"),
+                expected: ty.clone(),
+                found: value.ty(),
+            },
+            span,
+        })?;
+        self.vars.insert(name.to_string(), Var { ty, value });
+        Ok(())
+    }
+}
+
+/// `found`型の値を`ty`型の変数に代入できるか
+pub(super) fn can_assign(ty: &Type, found: &Type) -> bool {
+    ty.accepts(found)
+        || matches!(ty, Type::Option(inner) if inner.accepts(found))
+}
+/// 値を変数の型に合わせる
+///
+/// option型の変数には値をsomeで包んで代入できる
+pub(super) fn coerce(
+    ty: &Type,
+    value: Value,
+) -> ::std::result::Result<Value, Value> {
+    let found = value.ty();
+    if ty.accepts(&found) {
+        Ok(value)
+    }
+    else if let Type::Option(inner) = ty
+        && inner.accepts(&found)
+    {
+        Ok(Value::some(value))
+    }
+    else {
+        Err(value)
+    }
+}
+
+/// 環境変数に設定する文字列
+///
+/// noneの場合は`None`になる
+fn env_value(
+    name: &str,
+    value: Option<(Value, Span)>,
+) -> Result<Option<String>> {
+    match value {
+        None | Some((Value::Option(None), _)) => Ok(None),
+        Some((Value::String(string), _)) => Ok(Some(string)),
+        Some((Value::Option(Some(value)), span)) => match *value {
+            Value::String(string) => Ok(Some(string)),
+            value => Err(env_type_mismatch(name, value.ty(), span)),
+        },
+        Some((value, span)) => Err(env_type_mismatch(name, value.ty(), span)),
+    }
+}
+fn env_type_mismatch(name: &str, found: Type, span: Span) -> EvalError {
+    EvalError {
+        kind: EvalErrorKind::VarTypeMismatch {
+            name: format!("${name}"),
+            expected: Type::String,
+            found,
+        },
+        span,
+    }
+}
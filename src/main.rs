@@ -1,35 +1,207 @@
 mod builtin;
 mod exec;
 mod parse;
+mod script;
+mod value;
+
+use anyhow::Context;
+use clap::{Arg, ArgAction};
+use script::LineBuffer;
+use std::path::{Path, PathBuf};
+
+fn cli() -> clap::Command {
+    clap::Command::new("asari")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Rust風の構文を持つシェル")
+        .arg(
+            Arg::new("command")
+                .short('c')
+                .value_name("COMMAND")
+                .help("文字列をコマンドとして実行する"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .help("実行せずに構文と型だけ検査する"),
+        )
+        .arg(
+            Arg::new("norc")
+                .long("norc")
+                .action(ArgAction::SetTrue)
+                .help("起動時の設定ファイルを読み込まない"),
+        )
+        .arg(
+            Arg::new("login")
+                .short('l')
+                .long("login")
+                .action(ArgAction::SetTrue)
+                .help("ログインシェルとしてlogin.asrも読み込む"),
+        )
+        .arg(
+            Arg::new("args")
+                .value_name("SCRIPT")
+                .num_args(0..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true)
+                .help("実行するスクリプトとその引数（-cの場合は引数のみ）"),
+        )
+}
 
 fn main() -> anyhow::Result<()> {
-    welcome();
+    // 深い関数の呼び出しに備えて大きなスタックのスレッドで実行する
+    std::thread::Builder::new()
+        .stack_size(exec::STACK_SIZE)
+        .spawn(shell_main)
+        .context("シェルのスレッドを起動できませんでした")?
+        .join()
+        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+fn shell_main() -> anyhow::Result<()> {
+    let matches = cli().get_matches();
+    let check = matches.get_flag("check");
+    // ログインシェルは名前の先頭に-を付けて起動される
+    let login = matches.get_flag("login")
+        || std::env::args()
+            .next()
+            .is_some_and(|name| name.starts_with('-'));
+    let command = matches.get_one::<String>("command");
+    let mut args = matches
+        .get_many::<String>("args")
+        .into_iter()
+        .flatten()
+        .cloned();
+    let script = if command.is_none() { args.next() } else { None };
+    let interactive = command.is_none() && script.is_none() && !check;
 
+    exec::handle_interrupt()
+        .context("Ctrl-Cのハンドラを設定できませんでした")?;
     let mut shell = exec::Shell::new();
+    if !matches.get_flag("norc") && !check {
+        for path in startup_files(login, interactive) {
+            if let Err(e) = run_startup_file(&mut shell, &path) {
+                std::process::exit(e.status());
+            }
+        }
+    }
+    let code = if let Some(command) = command {
+        shell.set_args(args.collect());
+        run(&mut shell, command, None, check)
+    }
+    else if let Some(path) = script {
+        let source = std::fs::read_to_string(&path).with_context(|| {
+            format!("スクリプト '{path}' を読み込めませんでした")
+        })?;
+        shell.set_script(path.clone(), args.collect());
+        run(&mut shell, &source, Some(&path), check)
+    }
+    // 標準入力のスクリプトを検査する
+    else if check {
+        let source = std::io::read_to_string(std::io::stdin())
+            .context("入力の取得に失敗しました")?;
+        script::check_source(&shell, &source, None)
+    }
+    else {
+        repl(&mut shell)?
+    };
+    std::process::exit(code);
+}
+
+/// ソースを実行するか、`check`なら検査だけして終了ステータスを返す
+fn run(
+    shell: &mut exec::Shell,
+    source: &str,
+    file: Option<&str>,
+    check: bool,
+) -> i32 {
+    if check {
+        script::check_source(shell, source, file)
+    }
+    else {
+        script::run_source(shell, source, file).unwrap_or_else(|e| e.status())
+    }
+}
+
+/// 起動時に読み込む設定ファイル
+///
+/// ログインシェルでは`login.asr`を、対話モードでは`config.asr`を読み込む
+fn startup_files(login: bool, interactive: bool) -> Vec<PathBuf> {
+    let Some(dir) = dirs::config_dir().map(|dir| dir.join("asari"))
+    else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    if login {
+        files.push(dir.join("login.asr"));
+    }
+    if interactive {
+        files.push(dir.join("config.asr"));
+    }
+    files
+}
+
+/// 設定ファイルを実行する
+///
+/// ファイルがなければ何もしない
+/// エラーは表示するだけでシェルの起動は続け、`exit`だけを`Err`で返す
+fn run_startup_file(
+    shell: &mut exec::Shell,
+    path: &Path,
+) -> Result<(), exec::Error> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            eprintln!(
+                "設定ファイル '{}' を読み込めませんでした : {e}",
+                path.display()
+            );
+            return Ok(());
+        }
+    };
+    let file = path.display().to_string();
+    script::run_source(shell, &source, Some(&file))?;
+    Ok(())
+}
 
+/// 対話モードで実行して終了ステータスを返す
+fn repl(shell: &mut exec::Shell) -> anyhow::Result<i32> {
+    welcome();
+
+    let mut buffer = LineBuffer::default();
     loop {
-        continuation(&std::env::current_dir()?);
+        if buffer.is_pending() {
+            pending_continuation();
+        }
+        else {
+            continuation(shell.current_dir());
+        }
 
         let stdin = std::io::stdin();
         let mut line = String::new();
         match stdin.read_line(&mut line) {
+            // 入力の終わり
+            Ok(0) => {
+                println!();
+                return Ok(shell.last_status());
+            }
             Ok(_len) => {
                 let line = line.trim_end_matches(['\n', '\r']);
-                let parsed = parse::parse_shell_command(&line);
-                let command = match parsed {
-                    Ok(command) => command,
-                    Err(e) => {
-                        let display = e.inner().display(e.input());
-                        eprintln!("{display}");
-                        continue;
-                    }
+                let Some(parsed) = buffer.push(line)
+                else {
+                    continue;
                 };
-
-                use exec::Error;
-                match shell.execute(&command) {
-                    Err(Error::Exit(code)) => std::process::exit(code),
-                    Err(e) => eprintln!("コマンドの実行に失敗しました : {e}"),
-                    _ => {}
+                let source = parsed.source.as_str();
+                match &parsed.result {
+                    Ok(command) => {
+                        if let Err(exec::Error::Exit(code)) =
+                            shell.execute(command, source)
+                        {
+                            return Ok(code);
+                        }
+                    }
+                    Err(e) => eprint!("{}", e.display(source)),
                 }
             }
             Err(e) => {
@@ -51,6 +223,14 @@ fn continuation(current_dir: &std::path::Path) {
         .expect("stdoutのフラッシュに失敗しました");
 }
 
+fn pending_continuation() {
+    use std::io::Write;
+    print!("...>");
+    std::io::stdout()
+        .flush()
+        .expect("stdoutのフラッシュに失敗しました");
+}
+
 fn format_path(path: &std::path::Path) -> String {
     if let Some(home) = dirs::home_dir()
         && let Ok(relative) = path.strip_prefix(&home)
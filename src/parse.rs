@@ -8,23 +8,32 @@ use tools::*;
 use winnow::{
     LocatingSlice,
     combinator::{
-        alt, delimited, dispatch, empty, fail, not, opt, peek, preceded,
-        repeat, todo as todo_parser,
+        alt, delimited, dispatch, empty, eof, fail, not, opt, peek, preceded,
+        repeat, separated, terminated, todo as todo_parser,
     },
+    error::ErrMode,
     prelude::*,
-    token::{any, rest, take_till, take_until, take_while},
+    token::{any, one_of, rest, take_till, take_until, take_while},
 };
 
-use crate::parse::tools::ParserExt;
+use crate::{parse::tools::ParserExt, value::Type};
 
 type Input<'i> = LocatingSlice<&'i str>;
-type Span = std::ops::Range<usize>;
+pub type Span = std::ops::Range<usize>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Spanned<T> {
     inner: T,
     span: Span,
 }
+impl<T> Spanned<T> {
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
 impl<T: PartialOrd> PartialOrd for Spanned<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.inner.partial_cmp(&other.inner)
@@ -50,20 +59,189 @@ fn spanned<T>(input: (T, Span)) -> Spanned<T> {
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ShellCommand {
-    pub commands: Vec<(Command, Option<Pipe>)>,
+    pub commands: Vec<(Statement, Option<Pipe>)>,
     pub comment: Option<String>,
 }
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Pipe {
     Split,
     Pipe,
-    In,
-    Out,
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Statement {
+    Command(Command),
+    Assign(Assign),
+    If(If),
+    Loop(Loop),
+    Match(Match),
+    Group(Group),
+    /// `break 'label`
+    Break(Spanned<Option<String>>),
+    /// `continue 'label`
+    Continue(Spanned<Option<String>>),
+    Function(Function),
+    /// `return value`
+    Return(Spanned<Option<Spanned<Word>>>),
+}
+/// `fn name(%a: type, %b: type = default) { … }`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Function {
+    pub name: Spanned<String>,
+    pub params: Vec<Param>,
+    pub block: Block,
+}
+/// `{ … }`のコマンドグループや`subshell { … }`のサブシェル
+///
+/// リダイレクトやパイプはグループ全体に適用される
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Group {
+    pub block: Block,
+    /// 変数やカレントディレクトリの変更を元に戻すか
+    pub subshell: bool,
+    pub redirects: Vec<Spanned<Redirect>>,
+}
+/// `match value { pattern => …, … }`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Match {
+    pub value: Spanned<Word>,
+    pub arms: Vec<MatchArm>,
+}
+/// `pattern | pattern => { … }`
+///
+/// ブロックの代わりに1つの文も書ける
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MatchArm {
+    pub patterns: Vec<Spanned<Pattern>>,
+    pub block: Block,
+}
+/// `match`の腕のパターン
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pattern {
+    /// すべての値に一致する`_`
+    Wildcard,
+    /// すべての値に一致して変数に束縛する`%name`
+    Bind(String),
+    /// クォートで囲まれた文字列
+    String(String),
+    /// クォートなしの文字列で、`*`・`?`・`[…]`をグロブとして扱う
+    Glob(String),
+    Int(i64),
+    Bool(bool),
+    None,
+    /// `some(pattern)`
+    Some(Box<Spanned<Pattern>>),
+}
+/// 関数の引数
+///
+/// 最後の引数が配列型なら残りの引数をすべて受け取る
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Param {
+    pub name: Spanned<String>,
+    pub ty: Spanned<Type>,
+    pub default: Option<Spanned<Expr>>,
+}
+/// `{ … }`で囲まれた文の並び
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Block {
+    pub commands: Vec<(Statement, Option<Pipe>)>,
+}
+/// `if cond { … } else if cond { … } else { … }`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct If {
+    pub branches: Vec<(Condition, Block)>,
+    pub else_block: Option<Block>,
+}
+/// `'label: for …`や`'label: while …`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Loop {
+    pub label: Option<String>,
+    pub kind: LoopKind,
+    pub block: Block,
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoopKind {
+    /// `for %var in iter`
+    For { var: Spanned<String>, iter: ForIter },
+    /// `while cond`
+    While(Condition),
+}
+/// `for`で繰り返す値
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ForIter {
+    /// 配列になる単語
+    Word(Spanned<Word>),
+    /// `start..end`や`start..=end`の整数の範囲
+    Range {
+        start: Spanned<Expr>,
+        end: Spanned<Expr>,
+        inclusive: bool,
+    },
+}
+/// `if`や`while`の条件
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    /// bool型の値になる変数や`( … )`の式
+    Expr(Spanned<Word>),
+    /// 終了ステータスが0なら真になる、`|`でつないだコマンド
+    Command(Vec<Statement>),
 }
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Command {
+    pub env: Vec<TempEnv>,
     pub name: Spanned<Word>,
     pub args: Vec<Spanned<Word>>,
+    pub redirects: Vec<Spanned<Redirect>>,
+}
+/// コマンドの実行中だけ設定する環境変数
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TempEnv {
+    pub name: String,
+    /// `none`の場合は`None`
+    pub value: Option<Spanned<Word>>,
+}
+/// `$VAR = value`や`%var: type = value`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Assign {
+    pub target: Spanned<AssignTarget>,
+    pub ty: Option<Spanned<Type>>,
+    pub value: AssignValue,
+}
+/// 代入する値
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssignValue {
+    Word(Spanned<Word>),
+    /// `none`（位置のみ持つ）
+    None(Spanned<()>),
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssignTarget {
+    EnvVar(String),
+    ShellVar(String),
+    /// `%name[index]`
+    Element(String, Box<Spanned<Expr>>),
+}
+impl Display for AssignTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssignTarget::EnvVar(name) => write!(f, "${name}"),
+            AssignTarget::ShellVar(name) => write!(f, "%{name}"),
+            AssignTarget::Element(name, index) => write!(f, "%{name}[{index}]"),
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Redirect {
+    /// リダイレクトするファイルディスクリプタ（0〜2）
+    pub fd: u32,
+    pub target: RedirectTarget,
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RedirectTarget {
+    Read(Spanned<Word>),     // < file
+    Write(Spanned<Word>),    // > file
+    Append(Spanned<Word>),   // >> file
+    Duplicate(Spanned<u32>), // >&fd
 }
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Word {
@@ -73,13 +251,137 @@ pub enum Word {
     SpecialVar(SpecialVar),
     EnvVar(String),
     ShellVar(String),
+    Postfix(Box<Spanned<Word>>, Postfix),
+    UnwrapOr(Box<Spanned<Word>>, Box<Spanned<Word>>),
+    /// `( … )`の式
+    Expr(Box<Spanned<Expr>>),
 }
 impl Display for Word {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Word::*;
         match self {
             Literal(literal) => write!(f, "{literal}"),
-            _ => todo!(),
+            PathLiteral(path) => write!(f, "p\"{path}\""),
+            SpecialVar(var) => write!(f, "{var}"),
+            EnvVar(name) => write!(f, "${name}"),
+            ShellVar(name) => write!(f, "%{name}"),
+            Postfix(word, op) => write!(f, "{word}{op}"),
+            UnwrapOr(word, default) => write!(f, "{word} ^ {default}"),
+            Expr(expr) => write!(f, "({expr})"),
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Expr {
+    /// 文字列・パス・変数
+    Word(Word),
+    Int(i64),
+    Float(Float),
+    Bool(bool),
+    Unary(UnaryOp, Box<Spanned<Expr>>),
+    Binary(Box<Spanned<Expr>>, BinaryOp, Box<Spanned<Expr>>),
+    Postfix(Box<Spanned<Expr>>, Postfix),
+    UnwrapOr(Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+    As(Box<Spanned<Expr>>, Type),
+    Array(Vec<Spanned<Expr>>),
+    Some(Box<Spanned<Expr>>),
+    None,
+}
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Expr::*;
+        match self {
+            Word(word) => write!(f, "{word}"),
+            Int(int) => write!(f, "{int}"),
+            Float(float) => write!(f, "{:?}", float.0),
+            Bool(bool) => write!(f, "{bool}"),
+            Unary(op, expr) => write!(f, "{op}{expr}"),
+            Binary(left, op, right) => write!(f, "{left} {op} {right}"),
+            Postfix(expr, op) => write!(f, "{expr}{op}"),
+            UnwrapOr(expr, default) => write!(f, "{expr} ^ {default}"),
+            As(expr, ty) => write!(f, "{expr} as {ty}"),
+            Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "]")
+            }
+            Some(expr) => write!(f, "some({expr})"),
+            None => write!(f, "none"),
+        }
+    }
+}
+/// 浮動小数点数のリテラル
+///
+/// 構文木を比較できるようにビット列で比較する
+#[derive(Clone, Copy, Debug)]
+pub struct Float(pub f64);
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+impl Eq for Float {}
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnaryOp {
+    Neg, // -
+    Not, // !
+}
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BinaryOp {
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Rem, // %
+    Eq,  // ==
+    Ne,  // !=
+    Lt,  // <
+    Le,  // <=
+    Gt,  // >
+    Ge,  // >=
+    And, // &&
+    Or,  // ||
+}
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use BinaryOp::*;
+        match self {
+            Add => write!(f, "+"),
+            Sub => write!(f, "-"),
+            Mul => write!(f, "*"),
+            Div => write!(f, "/"),
+            Rem => write!(f, "%"),
+            Eq => write!(f, "=="),
+            Ne => write!(f, "!="),
+            Lt => write!(f, "<"),
+            Le => write!(f, "<="),
+            Gt => write!(f, ">"),
+            Ge => write!(f, ">="),
+            And => write!(f, "&&"),
+            Or => write!(f, "||"),
         }
     }
 }
@@ -90,14 +392,102 @@ pub enum SpecialVar {
     BackgroundPid, // $!
     ShellName,     // $@
 }
+impl Display for SpecialVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use SpecialVar::*;
+        match self {
+            ExitStatus => write!(f, "$?"),
+            Pid => write!(f, "$$"),
+            BackgroundPid => write!(f, "$!"),
+            ShellName => write!(f, "$@"),
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Postfix {
+    IsSome,                    // ?
+    Unwrap,                    // !
+    Len,                       // @
+    Index(Box<Spanned<Expr>>), // [index]
+}
+impl Display for Postfix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Postfix::*;
+        match self {
+            IsSome => write!(f, "?"),
+            Unwrap => write!(f, "!"),
+            Len => write!(f, "@"),
+            Index(index) => write!(f, "[{index}]"),
+        }
+    }
+}
 
 type ModalResult<O> = winnow::ModalResult<O, ParseError>;
 
+/// 空白文字と複数行コメント
 fn space0<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
-    take_while(0.., char::is_whitespace).parse_next(input)
+    repeat(0.., space_or_comment)
+        .map(|()| ())
+        .take()
+        .parse_next(input)
 }
 fn space1<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
-    take_while(1.., char::is_whitespace).parse_next(input)
+    repeat(1.., space_or_comment)
+        .map(|()| ())
+        .take()
+        .parse_next(input)
+}
+/// 改行を含まない空白文字と複数行コメント
+///
+/// コマンドの中では改行が文の区切りになる
+fn blank0<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
+    repeat(0.., blank_or_comment)
+        .map(|()| ())
+        .take()
+        .parse_next(input)
+}
+fn blank1<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
+    repeat(1.., blank_or_comment)
+        .map(|()| ())
+        .take()
+        .parse_next(input)
+}
+fn blank_or_comment(input: &mut Input) -> ModalResult<()> {
+    alt((
+        take_while(1.., |c: char| c.is_whitespace() && c != '\n').void(),
+        block_comment,
+    ))
+    .parse_next(input)
+}
+/// 文の間の空白文字と、改行で終わる1行コメント
+fn trivia<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
+    (space0, repeat(0.., (comment, '\n', space0)).map(|()| ()))
+        .take()
+        .parse_next(input)
+}
+fn space_or_comment(input: &mut Input) -> ModalResult<()> {
+    alt((take_while(1.., char::is_whitespace).void(), block_comment))
+        .parse_next(input)
+}
+/// ネストできる`#| … |#`
+fn block_comment(input: &mut Input) -> ModalResult<()> {
+    let (_, span) = "#|".with_span().parse_next(input)?;
+    let mut depth = 1;
+    while depth > 0 {
+        if opt("|#").parse_next(input)?.is_some() {
+            depth -= 1;
+        }
+        else if opt("#|").parse_next(input)?.is_some() {
+            depth += 1;
+        }
+        else if opt(any).parse_next(input)?.is_none() {
+            return Err(ErrMode::Cut(ParseError {
+                kind: ParseErrorKind::NoEndComment,
+                span: span.start,
+            }));
+        }
+    }
+    Ok(())
 }
 fn unicode_number(input: &mut Input) -> ModalResult<char> {
     take_until(0.., '}')
@@ -186,39 +576,686 @@ pub fn parse_shell_command(
     shell_command.parse(Input::new(input))
 }
 fn shell_command(input: &mut Input) -> ModalResult<ShellCommand> {
-    let _ = space0.parse_next(input)?;
-    let commands = repeat(
-        0..=1,
-        preceded(peek(not('#')), (command, empty.value(None))),
-    )
-    .parse_next(input)?;
-    //commands: repeat(0.., preceded(peek(not('#')), (command, opt(pipe)))).parse_next(input)?,
-    let comment = opt(preceded(space0, comment)).parse_next(input)?;
+    let _ = trivia.parse_next(input)?;
+    let commands = opt(command_list).parse_next(input)?.unwrap_or_default();
+    let comment = opt(preceded(trivia, comment)).parse_next(input)?;
     let _ = space0.parse_next(input)?;
     Ok(ShellCommand { commands, comment })
 }
+fn command_list(
+    input: &mut Input,
+) -> ModalResult<Vec<(Statement, Option<Pipe>)>> {
+    let mut commands = Vec::new();
+    let mut current = preceded(peek(not('#')), statement).parse_next(input)?;
+    while let Some(pipe) = opt(preceded(blank0, pipe)).parse_next(input)? {
+        let next = match pipe {
+            // パイプの後にはコマンドが必要
+            Pipe::Pipe => preceded(
+                space0,
+                alt((
+                    preceded(peek(not('#')), statement),
+                    empty
+                        .try_map_with_span(|()| {
+                            Err(ParseErrorKind::NoCommandAfterPipe)
+                        })
+                        .cut(),
+                )),
+            )
+            .parse_next(input)?,
+            // 末尾の;は許可する
+            _ => {
+                let next = opt(preceded((trivia, peek(not('#'))), statement))
+                    .parse_next(input)?;
+                let Some(next) = next
+                else {
+                    commands.push((current, Some(pipe)));
+                    return Ok(commands);
+                };
+                next
+            }
+        };
+        commands.push((std::mem::replace(&mut current, next), Some(pipe)));
+    }
+    commands.push((current, None));
+    Ok(commands)
+}
 fn pipe(input: &mut Input) -> ModalResult<Pipe> {
-    todo!()
+    alt((
+        '|'.value(Pipe::Pipe),
+        ';'.value(Pipe::Split),
+        // 改行も;と同じ区切り
+        (opt(comment), '\n').value(Pipe::Split),
+    ))
+    .parse_next(input)
 }
 fn comment(input: &mut Input) -> ModalResult<String> {
-    preceded('#', rest).map(str::to_string).parse_next(input)
+    preceded('#', take_till(0.., '\n'))
+        .map(str::to_string)
+        .parse_next(input)
+}
+fn statement(input: &mut Input) -> ModalResult<Statement> {
+    alt((
+        if_statement.map(Statement::If),
+        loop_statement.map(Statement::Loop),
+        match_statement.map(Statement::Match),
+        preceded(keyword("break"), loop_label)
+            .with_span()
+            .map(spanned)
+            .map(Statement::Break),
+        preceded(keyword("continue"), loop_label)
+            .with_span()
+            .map(spanned)
+            .map(Statement::Continue),
+        function.map(Statement::Function),
+        preceded(
+            keyword("return"),
+            opt(preceded((blank1, peek(not('#'))), word)),
+        )
+        .with_span()
+        .map(spanned)
+        .map(Statement::Return),
+        group.map(Statement::Group),
+        assign.map(Statement::Assign),
+        command.map(Statement::Command),
+    ))
+    .parse_next(input)
+}
+/// `{ … }`のブロック
+///
+/// `}`がなければ次の行に続く
+fn block(input: &mut Input) -> ModalResult<Block> {
+    let _ = '{'
+        .map_err_with_span(|()| ParseErrorKind::NoBlock)
+        .cut()
+        .parse_next(input)?;
+    let _ = trivia.parse_next(input)?;
+    let commands = opt(command_list).parse_next(input)?.unwrap_or_default();
+    let _ = (
+        trivia,
+        opt(comment),
+        '}'.map_err_with_span(|()| ParseErrorKind::NoEndBrace).cut(),
+    )
+        .parse_next(input)?;
+    Ok(Block { commands })
+}
+fn group(input: &mut Input) -> ModalResult<Group> {
+    let subshell = alt((
+        peek('{').value(false),
+        (keyword("subshell"), blank0, peek('{')).value(true),
+    ))
+    .parse_next(input)?;
+    let block = block.parse_next(input)?;
+    let redirects =
+        repeat(0.., preceded(blank0, redirect.with_span().map(spanned)))
+            .parse_next(input)?;
+    Ok(Group {
+        block,
+        subshell,
+        redirects,
+    })
+}
+fn if_statement(input: &mut Input) -> ModalResult<If> {
+    let _ = keyword("if").parse_next(input)?;
+    let mut branches = vec![conditional_block.parse_next(input)?];
+    let mut else_block = None;
+    // elseは}と同じ行に書く
+    while opt((blank0, keyword("else"))).parse_next(input)?.is_some() {
+        if opt((blank0, keyword("if"))).parse_next(input)?.is_some() {
+            branches.push(conditional_block.parse_next(input)?);
+        }
+        else {
+            else_block = Some(preceded(blank0, block).parse_next(input)?);
+            break;
+        }
+    }
+    Ok(If {
+        branches,
+        else_block,
+    })
+}
+/// `if`や`while`の後の条件とブロック
+fn conditional_block(input: &mut Input) -> ModalResult<(Condition, Block)> {
+    let condition = preceded(blank0, condition).parse_next(input)?;
+    let block = preceded(blank0, block).parse_next(input)?;
+    Ok((condition, block))
+}
+fn loop_statement(input: &mut Input) -> ModalResult<Loop> {
+    let label = opt(terminated(label, (':', blank0))).parse_next(input)?;
+    let (kind, block) = alt((
+        preceded(keyword("while"), conditional_block)
+            .map(|(condition, block)| (LoopKind::While(condition), block)),
+        preceded(keyword("for"), for_header),
+    ))
+    .parse_next(input)?;
+    Ok(Loop { label, kind, block })
+}
+fn match_statement(input: &mut Input) -> ModalResult<Match> {
+    let value = preceded(
+        (keyword("match"), blank0),
+        alt((
+            preceded(peek(not('#')), word),
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoMatchValue))
+                .cut(),
+        )),
+    )
+    .parse_next(input)?;
+    let _ = (
+        blank0,
+        '{'.map_err_with_span(|()| ParseErrorKind::NoBlock).cut(),
+        trivia,
+    )
+        .parse_next(input)?;
+    // 腕は改行か;で区切り、ブロックの後にはカンマも書ける
+    let arms = repeat(
+        0..,
+        preceded(
+            peek(not(alt(('}'.void(), '#'.void(), eof.void())))),
+            terminated(match_arm, (blank0, opt(one_of([',', ';'])), trivia)),
+        ),
+    )
+    .parse_next(input)?;
+    let _ = (
+        trivia,
+        opt(comment),
+        '}'.map_err_with_span(|()| ParseErrorKind::NoEndBrace).cut(),
+    )
+        .parse_next(input)?;
+    Ok(Match { value, arms })
+}
+fn match_arm(input: &mut Input) -> ModalResult<MatchArm> {
+    let patterns =
+        separated(1.., pattern.with_span().map(spanned), (space0, '|', space0))
+            .parse_next(input)?;
+    let _ = (
+        space0,
+        "=>".map_err_with_span(|()| ParseErrorKind::NoFatArrow)
+            .cut(),
+        blank0,
+    )
+        .parse_next(input)?;
+    let block = alt((
+        preceded(peek('{'), block),
+        preceded(peek(not(one_of(['#', '\n', '}']))), arm_statement).map(
+            |statement| Block {
+                commands: vec![(statement, None)],
+            },
+        ),
+        empty
+            .try_map_with_span(|()| Err(ParseErrorKind::NoArmBody))
+            .cut(),
+    ))
+    .parse_next(input)?;
+    Ok(MatchArm { patterns, block })
+}
+/// ブロックで囲まない腕の文
+///
+/// `,`で区切った次の腕の`=>`がリダイレクトとして読まれた場合はエラーにする
+fn arm_statement(input: &mut Input) -> ModalResult<Statement> {
+    let statement = statement.parse_next(input)?;
+    if let Statement::Command(command) = &statement
+        && let Some(arrow) = fat_arrow(command)
+    {
+        return Err(ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::FatArrowInArm,
+            span: arrow,
+        }));
+    }
+    Ok(statement)
+}
+/// `=`で終わる単語とその直後の`>`のリダイレクトでできた`=>`の位置
+fn fat_arrow(command: &Command) -> Option<usize> {
+    let words = || std::iter::once(&command.name).chain(&command.args);
+    command.redirects.iter().find_map(|redirect| {
+        let RedirectTarget::Write(_) = redirect.inner().target
+        else {
+            return None;
+        };
+        words().find_map(|word| match word.inner() {
+            Word::Literal(literal)
+                if literal.ends_with('=')
+                    && word.span().end == redirect.span().start =>
+            {
+                Some(word.span().end - 1)
+            }
+            _ => None,
+        })
+    })
+}
+fn pattern(input: &mut Input) -> ModalResult<Pattern> {
+    alt((
+        keyword("_").value(Pattern::Wildcard),
+        keyword("none").value(Pattern::None),
+        keyword("true").value(Pattern::Bool(true)),
+        keyword("false").value(Pattern::Bool(false)),
+        delimited(
+            ("some", space0, '(', space0),
+            pattern.with_span().map(spanned),
+            (
+                space0,
+                ')'.map_err_with_span(|()| ParseErrorKind::NoEndParen).cut(),
+            ),
+        )
+        .map(|pattern| Pattern::Some(Box::new(pattern))),
+        preceded('%', ident).map(Pattern::Bind),
+        int_pattern,
+        alt((quoted_string, double_quoted_string, raw_string))
+            .map(Pattern::String),
+        repeat(
+            1..,
+            preceded(not("=>"), any.verify(|c| is_unquoted_char(*c))),
+        )
+        .map(Pattern::Glob),
+        empty
+            .try_map_with_span(|()| Err(ParseErrorKind::NoPattern))
+            .cut(),
+    ))
+    .parse_next(input)
+}
+/// 後ろに文字列が続かない整数
+fn int_pattern(input: &mut Input) -> ModalResult<Pattern> {
+    let (int, span) = terminated(
+        (opt('-'), take_while(1.., |c: char| c.is_ascii_digit())).take(),
+        peek(not(any.verify(|c| is_unquoted_char(*c) && *c != '='))),
+    )
+    .with_span()
+    .parse_next(input)?;
+    int.parse().map(Pattern::Int).map_err(|_| {
+        ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::InvalidNumber,
+            span: span.start,
+        })
+    })
+}
+/// `for`の後の`%var in iter { … }`
+fn for_header(input: &mut Input) -> ModalResult<(LoopKind, Block)> {
+    let var = preceded(
+        blank0,
+        alt((
+            preceded('%', ident).with_span().map(spanned),
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoLoopVar))
+                .cut(),
+        )),
+    )
+    .parse_next(input)?;
+    let _ = preceded(
+        blank1,
+        alt((
+            keyword("in"),
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoIn))
+                .cut(),
+        )),
+    )
+    .parse_next(input)?;
+    let iter = preceded(blank0, for_iter).parse_next(input)?;
+    let block = preceded(blank0, block).parse_next(input)?;
+    Ok((LoopKind::For { var, iter }, block))
+}
+fn for_iter(input: &mut Input) -> ModalResult<ForIter> {
+    alt((
+        range,
+        // 配列リテラルは括弧なしで書ける
+        array
+            .with_span()
+            .map(spanned)
+            .map(|array| Word::Expr(Box::new(array)))
+            .with_span()
+            .map(spanned)
+            .map(ForIter::Word),
+        preceded(peek(not(one_of(['#', '{']))), word).map(ForIter::Word),
+        empty
+            .try_map_with_span(|()| Err(ParseErrorKind::NoIterator))
+            .cut(),
+    ))
+    .parse_next(input)
+}
+/// `start..end`や`start..=end`
+fn range(input: &mut Input) -> ModalResult<ForIter> {
+    let start = preceded(
+        peek(alt((
+            one_of(|c: char| c.is_ascii_digit() || "$%(".contains(c)).void(),
+            ('-', one_of(|c: char| c.is_ascii_digit())).void(),
+        ))),
+        add_expr,
+    )
+    .parse_next(input)?;
+    let inclusive = delimited((space0, ".."), opt('='), space0)
+        .map(|eq| eq.is_some())
+        .parse_next(input)?;
+    let end = add_expr.parse_next(input)?;
+    Ok(ForIter::Range {
+        start,
+        end,
+        inclusive,
+    })
+}
+/// `'label`
+fn label(input: &mut Input) -> ModalResult<String> {
+    preceded(
+        '\'',
+        (
+            one_of(|c: char| c == '_' || unicode_ident::is_xid_start(c)),
+            take_while(0.., unicode_ident::is_xid_continue),
+        )
+            .take(),
+    )
+    .map(str::to_string)
+    .parse_next(input)
+}
+/// `break`や`continue`の後のラベル
+fn loop_label(input: &mut Input) -> ModalResult<Option<String>> {
+    opt(preceded(blank1, terminated(label, peek(not('\''))))).parse_next(input)
+}
+fn function(input: &mut Input) -> ModalResult<Function> {
+    let _ = (keyword("fn"), blank0).parse_next(input)?;
+    let name = ident.with_span().map(spanned).parse_next(input)?;
+    let _ = (
+        blank0,
+        '('.map_err_with_span(|()| ParseErrorKind::NoParams).cut(),
+        space0,
+    )
+        .parse_next(input)?;
+    let params: Vec<_> =
+        separated(0.., param, (space0, ',', space0, peek(not(')'))))
+            .parse_next(input)?;
+    let _ = (
+        space0,
+        opt(','),
+        space0,
+        ')'.map_err_with_span(|()| ParseErrorKind::NoEndParen).cut(),
+    )
+        .parse_next(input)?;
+    // 残りの引数を受け取れるのは最後の引数だけ
+    if let Some(param) = params
+        .iter()
+        .rev()
+        .skip(1)
+        .find(|param| matches!(param.ty.inner(), Type::Array(_)))
+    {
+        return Err(ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::ArrayParamNotLast,
+            span: param.ty.span.start,
+        }));
+    }
+    let block = preceded(blank0, block).parse_next(input)?;
+    Ok(Function {
+        name,
+        params,
+        block,
+    })
+}
+/// `%name: type = default`
+fn param(input: &mut Input) -> ModalResult<Param> {
+    let name = preceded('%', ident)
+        .with_span()
+        .map(spanned)
+        .parse_next(input)?;
+    let ty = preceded(
+        (
+            space0,
+            ':'.map_err_with_span(|()| ParseErrorKind::NoParamType)
+                .cut(),
+            space0,
+        ),
+        ty.with_span().map(spanned),
+    )
+    .parse_next(input)?;
+    let default =
+        opt(preceded((space0, '=', space0), expr)).parse_next(input)?;
+    Ok(Param { name, ty, default })
+}
+/// 直後に`{`が続く変数や式はbool型の条件、それ以外はコマンド
+fn condition(input: &mut Input) -> ModalResult<Condition> {
+    alt((
+        terminated(
+            preceded(peek(one_of(['$', '%', '('])), word),
+            peek((blank0, '{')),
+        )
+        .map(Condition::Expr),
+        preceded(peek(not(one_of(['#', '{']))), condition_pipeline)
+            .map(Condition::Command),
+        empty
+            .try_map_with_span(|()| Err(ParseErrorKind::NoCondition))
+            .cut(),
+    ))
+    .parse_next(input)
+}
+fn condition_pipeline(input: &mut Input) -> ModalResult<Vec<Statement>> {
+    let mut pipeline = vec![command.map(Statement::Command).parse_next(input)?];
+    while opt((blank0, '|')).parse_next(input)?.is_some() {
+        let next = preceded(
+            space0,
+            alt((
+                preceded(peek(not('#')), command),
+                empty
+                    .try_map_with_span(|()| {
+                        Err(ParseErrorKind::NoCommandAfterPipe)
+                    })
+                    .cut(),
+            )),
+        )
+        .parse_next(input)?;
+        pipeline.push(Statement::Command(next));
+    }
+    Ok(pipeline)
+}
+fn assign(input: &mut Input) -> ModalResult<Assign> {
+    let target = dispatch!(any;
+        '$' => preceded(peek(not(special_var)), ident).map(AssignTarget::EnvVar),
+        '%' => (ident, opt(preceded('[', terminated(preceded(space0, expr), close_bracket))))
+            .map(|(name, index)| match index {
+                Some(index) => AssignTarget::Element(name, Box::new(index)),
+                None => AssignTarget::ShellVar(name),
+            }),
+        _ => fail,
+    )
+    .with_span()
+    .map(spanned)
+    .parse_next(input)?;
+    // 型注釈はシェル変数のみ
+    let ty = match target.inner() {
+        AssignTarget::ShellVar(_) => opt(preceded(
+            (blank0, ':', peek(not('=')), blank0),
+            ty.with_span().map(spanned),
+        ))
+        .parse_next(input)?,
+        AssignTarget::EnvVar(_) | AssignTarget::Element(..) => None,
+    };
+    let _ = (blank0, '=', peek(not('='))).parse_next(input)?;
+    let (value, span) =
+        preceded(blank0, assign_value.with_span()).parse_next(input)?;
+    let value = match value {
+        Some(word) => AssignValue::Word(word),
+        None => AssignValue::None(spanned(((), span))),
+    };
+    Ok(Assign { target, ty, value })
+}
+/// 代入する値（`none`の場合は`None`）
+fn assign_value(input: &mut Input) -> ModalResult<Option<Spanned<Word>>> {
+    alt((
+        keyword("none").value(None),
+        // 配列リテラルとsome(…)は代入する値としてのみ括弧なしで書ける
+        alt((array, preceded(peek("some("), expr_keyword)))
+            .with_span()
+            .map(spanned)
+            .map(|array| Word::Expr(Box::new(array)))
+            .with_span()
+            .map(spanned)
+            .map(Some),
+        preceded(peek(not('#')), word).map(Some),
+        empty
+            .try_map_with_span(|()| Err(ParseErrorKind::NoAssignValue))
+            .cut(),
+    ))
+    .parse_next(input)
+}
+/// コマンドの前の`$NAME := value`
+fn temp_env(input: &mut Input) -> ModalResult<TempEnv> {
+    let name =
+        preceded(('$', peek(not(special_var))), ident).parse_next(input)?;
+    let _ = (blank0, ":=").parse_next(input)?;
+    let value = preceded(blank0, assign_value).parse_next(input)?;
+    Ok(TempEnv { name, value })
+}
+/// 型の名前
+fn ty(input: &mut Input) -> ModalResult<Type> {
+    let (name, span) = take_while(0.., unicode_ident::is_xid_continue)
+        .with_span()
+        .parse_next(input)?;
+    let type_arg = |input: &mut Input| {
+        let _ = (space0, '<')
+            .map_err_with_span(|_| ParseErrorKind::NoTypeArgument)
+            .cut()
+            .parse_next(input)?;
+        let ty = preceded(space0, ty).parse_next(input)?;
+        let _ = (space0, '>')
+            .map_err_with_span(|_| ParseErrorKind::NoEndAngleBracket)
+            .cut()
+            .parse_next(input)?;
+        Ok(Box::new(ty))
+    };
+    match name {
+        "string" => Ok(Type::String),
+        "int" => Ok(Type::Int),
+        "float" => Ok(Type::Float),
+        "bool" => Ok(Type::Bool),
+        "path" => Ok(Type::Path),
+        "unit" => Ok(Type::Unit),
+        "array" => type_arg.map(Type::Array).parse_next(input),
+        "option" => type_arg.map(Type::Option).parse_next(input),
+        _ => Err(ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::UnknownType(name.to_string()),
+            span: span.start,
+        })),
+    }
+}
+/// 引用符なしの文字列の一部でないキーワード
+fn keyword<'a>(
+    keyword: &'static str,
+) -> impl Parser<Input<'a>, &'a str, ErrMode<ParseError>> {
+    terminated(keyword, peek(not(any.verify(|c| is_unquoted_char(*c)))))
 }
 pub fn command(input: &mut Input) -> ModalResult<Command> {
+    let env: Vec<_> =
+        repeat(0.., terminated(temp_env, blank0)).parse_next(input)?;
+    let name = if env.is_empty() {
+        word.parse_next(input)?
+    }
+    else {
+        alt((
+            preceded(peek(not('#')), word),
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoCommandAfterEnv))
+                .cut(),
+        ))
+        .parse_next(input)?
+    };
+    let mut args = Vec::new();
+    let mut redirects = Vec::new();
+    loop {
+        if let Some(redirect) =
+            opt(preceded(blank0, redirect.with_span().map(spanned)))
+                .parse_next(input)?
+        {
+            redirects.push(redirect);
+        }
+        else if let Some(arg) =
+            opt(preceded((blank1, peek(not('#'))), word)).parse_next(input)?
+        {
+            args.push(arg);
+        }
+        else {
+            break;
+        }
+    }
     Ok(Command {
-        name: word.parse_next(input)?,
-        args: repeat(0.., preceded((space1, peek(not('#'))), word))
-            .parse_next(input)?,
+        env,
+        name,
+        args,
+        redirects,
     })
 }
+fn redirect(input: &mut Input) -> ModalResult<Redirect> {
+    let (fd, fd_span) =
+        terminated(opt(one_of('0'..='9')), peek(one_of(['<', '>'])))
+            .with_span()
+            .parse_next(input)?;
+    let op = alt((">>", ">", "<")).parse_next(input)?;
+    let fd = match fd {
+        Some(fd) => file_descriptor(fd).ok_or(ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::InvalidFileDescriptor,
+            span: fd_span.start,
+        }))?,
+        None if op == "<" => 0,
+        None => 1,
+    };
+    let target = alt((
+        preceded('&', redirect_fd).map(RedirectTarget::Duplicate),
+        preceded(
+            blank0,
+            alt((
+                preceded(peek(not('#')), word),
+                empty
+                    .try_map_with_span(|()| {
+                        Err(ParseErrorKind::NoRedirectTarget)
+                    })
+                    .cut(),
+            )),
+        )
+        .map(|file| match op {
+            ">>" => RedirectTarget::Append(file),
+            ">" => RedirectTarget::Write(file),
+            _ => RedirectTarget::Read(file),
+        }),
+    ))
+    .parse_next(input)?;
+    Ok(Redirect { fd, target })
+}
+fn redirect_fd(input: &mut Input) -> ModalResult<Spanned<u32>> {
+    any.map_err_with_span(|()| ParseErrorKind::InvalidFileDescriptor)
+        .try_map_with_span(|c| {
+            file_descriptor(c).ok_or(ParseErrorKind::InvalidFileDescriptor)
+        })
+        .cut()
+        .with_span()
+        .map(spanned)
+        .parse_next(input)
+}
+/// 対応しているファイルディスクリプタ（標準入出力）の番号に変換
+fn file_descriptor(c: char) -> Option<u32> {
+    c.to_digit(10).filter(|fd| *fd <= 2)
+}
 fn word(input: &mut Input) -> ModalResult<Spanned<Word>> {
+    dispatch!(peek(any);
+        '$' | '%' => var_word,
+        '(' => with_postfix(
+            paren_expr
+                .map(|expr| Word::Expr(Box::new(expr)))
+                .with_span()
+                .map(spanned),
+        ),
+        // クォートなしの文字列は後置演算子の文字も含む
+        _ => with_postfix(literal_word),
+    )
+    .parse_next(input)
+}
+/// 後置演算子が続く単語
+fn with_postfix<'i>(
+    word: impl Parser<Input<'i>, Spanned<Word>, ErrMode<ParseError>>,
+) -> impl Parser<Input<'i>, Spanned<Word>, ErrMode<ParseError>> {
+    (word, repeat(0.., postfix.with_span())).map(|(word, ops): (_, Vec<_>)| {
+        ops.into_iter().fold(word, |word, (op, span)| Spanned {
+            span: word.span.start..span.end,
+            inner: Word::Postfix(Box::new(word), op),
+        })
+    })
+}
+fn literal_word(input: &mut Input) -> ModalResult<Spanned<Word>> {
     dispatch!(peek(any);
         '\'' => quoted_string.map(Word::Literal),
         '"' => double_quoted_string.map(Word::Literal),
-        '$' => preceded('$', alt((
-            special_var.map(Word::SpecialVar),
-            ident.map(Word::EnvVar),
-        ))),
-        '%' => preceded('%', ident).map(Word::ShellVar),
         _ => alt((
             raw_string.map(Word::Literal),
             path_string.map(Word::PathLiteral),
@@ -229,6 +1266,297 @@ fn word(input: &mut Input) -> ModalResult<Spanned<Word>> {
     .map(spanned)
     .parse_next(input)
 }
+/// 後置演算子と`^`によるデフォルト値を含む変数
+fn var_word(input: &mut Input) -> ModalResult<Spanned<Word>> {
+    let var = with_postfix(var).parse_next(input)?;
+    let default = opt(preceded(
+        (blank0, '^', blank0),
+        alt((
+            preceded(peek(not('#')), word),
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoDefaultValue))
+                .cut(),
+        )),
+    ))
+    .parse_next(input)?;
+    Ok(match default {
+        Some(default) => Spanned {
+            span: var.span.start..default.span.end,
+            inner: Word::UnwrapOr(Box::new(var), Box::new(default)),
+        },
+        None => var,
+    })
+}
+fn var(input: &mut Input) -> ModalResult<Spanned<Word>> {
+    dispatch!(peek(any);
+        '$' => preceded('$', alt((
+            special_var.map(Word::SpecialVar),
+            ident.map(Word::EnvVar),
+        ))),
+        '%' => preceded('%', ident).map(Word::ShellVar),
+        _ => fail,
+    )
+    .with_span()
+    .map(spanned)
+    .parse_next(input)
+}
+fn postfix(input: &mut Input) -> ModalResult<Postfix> {
+    dispatch!(any;
+        '?' => empty.value(Postfix::IsSome),
+        // !=と区別する
+        '!' => peek(not('=')).value(Postfix::Unwrap),
+        '@' => empty.value(Postfix::Len),
+        '[' => terminated(preceded(space0, expr), close_bracket)
+            .map(|index| Postfix::Index(Box::new(index))),
+        _ => fail,
+    )
+    .parse_next(input)
+}
+fn close_bracket(input: &mut Input) -> ModalResult<char> {
+    preceded(
+        space0,
+        ']'.map_err_with_span(|()| ParseErrorKind::NoEndBracket)
+            .cut(),
+    )
+    .parse_next(input)
+}
+/// 括弧で囲まれた式
+fn paren_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    delimited(
+        ('(', space0),
+        expr,
+        preceded(
+            space0,
+            ')'.map_err_with_span(|()| ParseErrorKind::NoEndParen).cut(),
+        ),
+    )
+    .parse_next(input)
+}
+fn expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    or_expr.parse_next(input)
+}
+/// 左結合の二項演算
+fn binary_left(
+    input: &mut Input,
+    mut operand: impl FnMut(&mut Input) -> ModalResult<Spanned<Expr>>,
+    mut op: impl FnMut(&mut Input) -> ModalResult<BinaryOp>,
+) -> ModalResult<Spanned<Expr>> {
+    let mut left = operand(input)?;
+    while let Some(op) = opt(preceded(space0, &mut op)).parse_next(input)? {
+        let right = preceded(space0, &mut operand).parse_next(input)?;
+        left = Spanned {
+            span: left.span.start..right.span.end,
+            inner: Expr::Binary(Box::new(left), op, Box::new(right)),
+        };
+    }
+    Ok(left)
+}
+fn or_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    binary_left(input, and_expr, |input| {
+        "||".value(BinaryOp::Or).parse_next(input)
+    })
+}
+fn and_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    binary_left(input, compare_expr, |input| {
+        "&&".value(BinaryOp::And).parse_next(input)
+    })
+}
+/// 比較演算は連結できない
+fn compare_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    let left = add_expr.parse_next(input)?;
+    let op = opt(preceded(
+        space0,
+        alt((
+            "==".value(BinaryOp::Eq),
+            "!=".value(BinaryOp::Ne),
+            "<=".value(BinaryOp::Le),
+            ">=".value(BinaryOp::Ge),
+            '<'.value(BinaryOp::Lt),
+            '>'.value(BinaryOp::Gt),
+        )),
+    ))
+    .parse_next(input)?;
+    let Some(op) = op
+    else {
+        return Ok(left);
+    };
+    let right = preceded(space0, add_expr).parse_next(input)?;
+    Ok(Spanned {
+        span: left.span.start..right.span.end,
+        inner: Expr::Binary(Box::new(left), op, Box::new(right)),
+    })
+}
+fn add_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    binary_left(input, mul_expr, |input| {
+        alt(('+'.value(BinaryOp::Add), '-'.value(BinaryOp::Sub)))
+            .parse_next(input)
+    })
+}
+fn mul_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    binary_left(input, as_expr, |input| {
+        alt((
+            '*'.value(BinaryOp::Mul),
+            '/'.value(BinaryOp::Div),
+            '%'.value(BinaryOp::Rem),
+        ))
+        .parse_next(input)
+    })
+}
+/// `expr as type`の型変換
+fn as_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    let mut expr = unary_expr.parse_next(input)?;
+    while let Some((ty, span)) = opt(preceded(
+        (
+            space1,
+            "as",
+            peek(not(any.verify(|c| unicode_ident::is_xid_continue(*c)))),
+            space0,
+        ),
+        ty.with_span(),
+    ))
+    .parse_next(input)?
+    {
+        expr = Spanned {
+            span: expr.span.start..span.end,
+            inner: Expr::As(Box::new(expr), ty),
+        };
+    }
+    Ok(expr)
+}
+fn unary_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    let op = opt(terminated(
+        alt(('-'.value(UnaryOp::Neg), '!'.value(UnaryOp::Not))),
+        space0,
+    ))
+    .with_span()
+    .parse_next(input)?;
+    match op {
+        (Some(op), span) => {
+            let operand = unary_expr.parse_next(input)?;
+            Ok(Spanned {
+                span: span.start..operand.span.end,
+                inner: Expr::Unary(op, Box::new(operand)),
+            })
+        }
+        (None, _) => unwrap_or_expr.parse_next(input),
+    }
+}
+/// `^`は右結合
+fn unwrap_or_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    let operand = postfix_expr.parse_next(input)?;
+    let default = opt(preceded((space0, '^', space0), unwrap_or_expr))
+        .parse_next(input)?;
+    Ok(match default {
+        Some(default) => Spanned {
+            span: operand.span.start..default.span.end,
+            inner: Expr::UnwrapOr(Box::new(operand), Box::new(default)),
+        },
+        None => operand,
+    })
+}
+fn postfix_expr(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    let (operand, ops): (_, Vec<_>) =
+        (atom, repeat(0.., postfix.with_span())).parse_next(input)?;
+    Ok(ops
+        .into_iter()
+        .fold(operand, |operand, (op, span)| Spanned {
+            span: operand.span.start..span.end,
+            inner: Expr::Postfix(Box::new(operand), op),
+        }))
+}
+fn atom(input: &mut Input) -> ModalResult<Spanned<Expr>> {
+    alt((
+        paren_expr.with_span().map(|(expr, span)| Spanned {
+            inner: expr.inner,
+            span,
+        }),
+        alt((
+            var.map(|var| Expr::Word(var.inner)),
+            quoted_string.map(|string| Expr::Word(Word::Literal(string))),
+            double_quoted_string
+                .map(|string| Expr::Word(Word::Literal(string))),
+            raw_string.map(|string| Expr::Word(Word::Literal(string))),
+            path_string.map(|path| Expr::Word(Word::PathLiteral(path))),
+            array,
+            number,
+            expr_keyword,
+            empty
+                .try_map_with_span(|()| Err(ParseErrorKind::NoExpr))
+                .cut(),
+        ))
+        .with_span()
+        .map(spanned),
+    ))
+    .parse_next(input)
+}
+/// `[a, b, c]`の配列リテラル
+fn array(input: &mut Input) -> ModalResult<Expr> {
+    // 末尾のカンマを許す
+    delimited(
+        ('[', space0),
+        separated(
+            0..,
+            preceded(peek(not(']')), expr),
+            (space0, ',', space0, peek(not(']'))),
+        ),
+        (space0, opt(','), close_bracket),
+    )
+    .map(Expr::Array)
+    .parse_next(input)
+}
+/// 整数または小数
+fn number(input: &mut Input) -> ModalResult<Expr> {
+    let (number, span) = (
+        take_while(1.., |c: char| c.is_ascii_digit()),
+        opt(('.', take_while(1.., |c: char| c.is_ascii_digit()))),
+    )
+        .take()
+        .with_span()
+        .parse_next(input)?;
+    let error = ErrMode::Cut(ParseError {
+        kind: ParseErrorKind::InvalidNumber,
+        span: span.start,
+    });
+    if peek(opt(any.verify(|c| unicode_ident::is_xid_continue(*c))))
+        .parse_next(input)?
+        .is_some()
+    {
+        return Err(error);
+    }
+    if number.contains('.') {
+        Ok(Expr::Float(Float(
+            number.parse().map_err(|_| error.clone())?,
+        )))
+    }
+    else {
+        Ok(Expr::Int(number.parse().map_err(|_| error)?))
+    }
+}
+/// 式の中の名前
+fn expr_keyword(input: &mut Input) -> ModalResult<Expr> {
+    let (name, span) = take_while(1.., unicode_ident::is_xid_continue)
+        .with_span()
+        .parse_next(input)?;
+    match name {
+        "true" => Ok(Expr::Bool(true)),
+        "false" => Ok(Expr::Bool(false)),
+        "none" => Ok(Expr::None),
+        "some" => {
+            let value = alt((
+                paren_expr,
+                empty
+                    .try_map_with_span(|()| Err(ParseErrorKind::NoSomeValue))
+                    .cut(),
+            ))
+            .parse_next(input)?;
+            Ok(Expr::Some(Box::new(value)))
+        }
+        _ => Err(ErrMode::Cut(ParseError {
+            kind: ParseErrorKind::UnquotedStringInExpr,
+            span: span.start,
+        })),
+    }
+}
 fn quoted_string(input: &mut Input) -> ModalResult<String> {
     const DELIMITER: char = '\'';
     delimited(
@@ -270,10 +1598,13 @@ fn path_string(input: &mut Input) -> ModalResult<String> {
     Ok(raw.to_string())
 }
 fn unquoted_string(input: &mut Input) -> ModalResult<String> {
-    take_till(1.., |c: char| c.is_whitespace() || "(){}|<>;&".contains(c))
+    take_while(1.., is_unquoted_char)
         .map(str::to_string)
         .parse_next(input)
 }
+fn is_unquoted_char(c: char) -> bool {
+    !c.is_whitespace() && !"(){}|<>;&".contains(c)
+}
 fn special_var(input: &mut Input) -> ModalResult<SpecialVar> {
     dispatch!(any;
         '?' => empty.value(SpecialVar::ExitStatus),
@@ -5,7 +5,7 @@ use winnow::{
     stream::Location,
 };
 
-use super::Input;
+use super::{Input, Span};
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum ParseErrorKind {
@@ -16,6 +16,36 @@ pub enum ParseErrorKind {
     UnrecognizedEscape(char),
     NoEndQuotation,
     NoEndDoubleQuotation,
+    NoCommandAfterPipe,
+    NoRedirectTarget,
+    InvalidFileDescriptor,
+    NoDefaultValue,
+    NoAssignValue,
+    NoCommandAfterEnv,
+    UnknownType(String),
+    NoTypeArgument,
+    NoEndAngleBracket,
+    NoExpr,
+    NoEndParen,
+    NoEndBracket,
+    InvalidNumber,
+    UnquotedStringInExpr,
+    NoSomeValue,
+    NoEndComment,
+    NoCondition,
+    NoBlock,
+    NoEndBrace,
+    NoLoopVar,
+    NoIn,
+    NoIterator,
+    NoParams,
+    NoParamType,
+    ArrayParamNotLast,
+    NoMatchValue,
+    NoPattern,
+    NoFatArrow,
+    NoArmBody,
+    FatArrowInArm,
     #[default]
     Other,
 }
@@ -54,7 +84,47 @@ impl Display for ParseErrorKind {
             NoEndDoubleQuotation => {
                 write!(f, "ダブルクォーテーションを閉じてください")
             }
-            e => write!(f, "不明なエラーです: {e}"),
+            NoCommandAfterPipe => write!(f, "パイプの後にコマンドが必要です"),
+            NoRedirectTarget => write!(f, "リダイレクト先が必要です"),
+            InvalidFileDescriptor => {
+                write!(f, "ファイルディスクリプタは0〜2で指定してください")
+            }
+            NoDefaultValue => write!(f, "^の後にデフォルト値が必要です"),
+            NoAssignValue => write!(f, "=の後に代入する値が必要です"),
+            NoCommandAfterEnv => {
+                write!(f, "環境変数の設定の後にコマンドが必要です")
+            }
+            UnknownType(name) => write!(f, "不明な型 '{name}' です"),
+            NoTypeArgument => write!(f, "<>で要素の型を指定してください"),
+            NoEndAngleBracket => write!(f, ">が必要です"),
+            NoExpr => write!(f, "式が必要です"),
+            NoEndParen => write!(f, ")が必要です"),
+            NoEndBracket => write!(f, "]が必要です"),
+            InvalidNumber => write!(f, "不正な数値です"),
+            UnquotedStringInExpr => {
+                write!(f, "式の中の文字列はクォートで囲んでください")
+            }
+            NoSomeValue => write!(f, "someの後に(値)が必要です"),
+            NoEndComment => write!(f, "コメントを|#で閉じてください"),
+            NoCondition => write!(f, "条件が必要です"),
+            NoBlock => write!(f, "{{ … }}のブロックが必要です"),
+            NoEndBrace => write!(f, "}}が必要です"),
+            NoLoopVar => write!(f, "forの後に%変数が必要です"),
+            NoIn => write!(f, "inが必要です"),
+            NoIterator => write!(f, "inの後に繰り返す値が必要です"),
+            NoParams => write!(f, "関数名の後に(引数)が必要です"),
+            NoParamType => write!(f, "引数の型を:で指定してください"),
+            ArrayParamNotLast => {
+                write!(f, "配列型の引数は最後にしか置けません")
+            }
+            NoMatchValue => write!(f, "matchの後に値が必要です"),
+            NoPattern => write!(f, "パターンが必要です"),
+            NoFatArrow => write!(f, "パターンの後に=>が必要です"),
+            NoArmBody => write!(f, "=>の後に実行する文が必要です"),
+            FatArrowInArm => {
+                write!(f, "腕は改行か;で区切ってください")
+            }
+            Other => write!(f, "不明なエラーです"),
         }
     }
 }
@@ -73,19 +143,59 @@ pub struct ParseError {
     //pub context: Option<String>,
 }
 impl ParseError {
-    pub fn display(&self, input: &Input) -> String {
-        let source = **input;
-        let mut display = String::new();
-        display += &format!("{}\n", source.replace(['\n', '\r'], " "));
-        display += &format!("{}^ {}\n", " ".repeat(self.span), self.kind);
-        /*display += &format!(
-            "{}{} {}\n",
-            " ".repeat(self.span.start),
-            "^".repeat(self.span.len()),
-            self.kind
-        );*/
-        display
+    pub fn display<'a>(&self, source: impl Into<Source<'a>>) -> String {
+        display_span(source, self.span..self.span, &self.kind)
+    }
+    /// 入力が途中で終わっているためのエラーか
+    ///
+    /// 次の行を続けて読めば解消できる
+    pub fn is_incomplete(&self, input: &str) -> bool {
+        use ParseErrorKind::*;
+        match self.kind {
+            NoEndComment | NoEndQuotation | NoEndDoubleQuotation => true,
+            NoEndParen | NoEndBracket | NoEndBrace | NoExpr => {
+                self.span == input.len()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// エラー表示に使う入力
+#[derive(Clone, Copy, Debug)]
+pub struct Source<'a> {
+    pub text: &'a str,
+    /// ファイルから読み込んだ場合はファイル名と`text`の最初の行番号
+    pub origin: Option<(&'a str, usize)>,
+}
+impl<'a> From<&'a str> for Source<'a> {
+    fn from(text: &'a str) -> Self {
+        Source { text, origin: None }
+    }
+}
+
+/// エラー箇所を示す表示を作る
+///
+/// ファイルから読み込んだ入力なら`ファイル名:行:列`を先頭に付ける
+pub fn display_span<'a>(
+    source: impl Into<Source<'a>>,
+    span: Span,
+    message: impl Display,
+) -> String {
+    let Source { text, origin } = source.into();
+    let before = &text[..span.start];
+    let begin = before.chars().count();
+    let len = text[span].chars().count().max(1);
+    let mut display = String::new();
+    if let Some((file, first_line)) = origin {
+        let line = first_line + before.matches('\n').count();
+        let column = before.rsplit('\n').next().unwrap_or_default();
+        let column = column.chars().count() + 1;
+        display += &format!("{file}:{line}:{column}\n");
     }
+    display += &format!("{}\n", text.replace(['\n', '\r'], " "));
+    display += &format!("{}{} {message}\n", " ".repeat(begin), "^".repeat(len));
+    display
 }
 impl ParserError<Input<'_>> for ParseError {
     type Inner = Self;
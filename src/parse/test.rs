@@ -33,6 +33,14 @@ fn special_var(var: SpecialVar, span: Span) -> Spanned<Word> {
         span,
     }
 }
+fn simple_command(command: SpannedInput, args: &[SpannedInput]) -> Command {
+    Command {
+        env: vec![],
+        name: literal(command),
+        args: args.iter().cloned().map(literal).collect(),
+        redirects: vec![],
+    }
+}
 fn shell(
     command: SpannedInput,
     args: &[SpannedInput],
@@ -40,10 +48,7 @@ fn shell(
 ) -> ShellCommand {
     ShellCommand {
         commands: vec![(
-            Command {
-                name: literal(command),
-                args: args.iter().cloned().map(literal).collect(),
-            },
+            Statement::Command(simple_command(command, args)),
             None,
         )],
         comment: comment.map(str::to_string),
@@ -653,10 +658,12 @@ fn comment_test() {
         shell_parse(r#"echo p"~/#dir""#),
         Ok(ShellCommand {
             commands: vec![(
-                Command {
+                Statement::Command(Command {
+                    env: vec![],
                     name: literal(("echo", 0..4)),
-                    args: vec![path_literal(("~/#dir", 5..14))]
-                },
+                    args: vec![path_literal(("~/#dir", 5..14))],
+                    redirects: vec![],
+                }),
                 None
             )],
             comment: None
@@ -692,10 +699,12 @@ fn comment_test() {
         shell_parse("echo $PATH # show path"),
         Ok(ShellCommand {
             commands: vec![(
-                Command {
+                Statement::Command(Command {
+                    env: vec![],
                     name: literal(("echo", 0..4)),
-                    args: vec![env_var(("PATH", 5..10))]
-                },
+                    args: vec![env_var(("PATH", 5..10))],
+                    redirects: vec![],
+                }),
                 None
             )],
             comment: Some(" show path".to_string())
@@ -705,13 +714,2006 @@ fn comment_test() {
         shell_parse("echo %var # shell var"),
         Ok(ShellCommand {
             commands: vec![(
-                Command {
+                Statement::Command(Command {
+                    env: vec![],
                     name: literal(("echo", 0..4)),
-                    args: vec![shell_var(("var", 5..9))]
-                },
+                    args: vec![shell_var(("var", 5..9))],
+                    redirects: vec![],
+                }),
                 None
             )],
             comment: Some(" shell var".to_string())
         })
     );
 }
+
+#[test]
+fn block_comment_test() {
+    let whitespace = Ok(ShellCommand {
+        commands: vec![],
+        comment: None,
+    });
+
+    // コメントのみ
+    assert_eq!(shell_parse("#| comment |#"), whitespace);
+    assert_eq!(shell_parse("#||#"), whitespace);
+    assert_eq!(shell_parse("#|\n複数行の\nコメント\n|#"), whitespace);
+
+    // インライン
+    assert_eq!(
+        shell_parse("echo #| inline |# hello"),
+        Ok(shell(("echo", 0..4), &[("hello", 18..23)], None))
+    );
+    assert_eq!(
+        shell_parse("#| first |# echo hello"),
+        Ok(shell(("echo", 12..16), &[("hello", 17..22)], None))
+    );
+    assert_eq!(
+        shell_parse("echo hello #| block |# # line"),
+        Ok(shell(("echo", 0..4), &[("hello", 5..10)], Some(" line")))
+    );
+    assert_eq!(
+        shell_parse("echo a #| 1 |# #| 2 |# b"),
+        Ok(shell(("echo", 0..4), &[("a", 5..6), ("b", 23..24)], None))
+    );
+
+    // ネスト
+    assert_eq!(shell_parse("#| outer #| inner |# outer |#"), whitespace);
+    assert_eq!(
+        shell_parse("echo #| a #| b #| c |# |# |# hello"),
+        Ok(shell(("echo", 0..4), &[("hello", 29..34)], None))
+    );
+
+    // 行コメントとクォートの中はブロックコメントにならない
+    assert_eq!(
+        shell_parse("# #| line"),
+        Ok(ShellCommand {
+            commands: vec![],
+            comment: Some(" #| line".to_string())
+        })
+    );
+    assert_eq!(
+        shell_parse("echo '#| a |#'"),
+        Ok(shell(("echo", 0..4), &[("#| a |#", 5..14)], None))
+    );
+
+    // 閉じていない
+    assert_eq!(
+        shell_parse("#| unclosed").map_err(|e| e.into_inner()),
+        Err(parse_error(ParseErrorKind::NoEndComment, 0))
+    );
+    assert_eq!(
+        shell_parse("echo #| a #| b |# hello").map_err(|e| e.into_inner()),
+        Err(parse_error(ParseErrorKind::NoEndComment, 5))
+    );
+}
+
+#[test]
+fn pipe_test() {
+    let pipeline = |commands: Vec<(Command, Option<Pipe>)>| ShellCommand {
+        commands: commands
+            .into_iter()
+            .map(|(command, pipe)| (Statement::Command(command), pipe))
+            .collect(),
+        comment: None,
+    };
+
+    // 基本的なパイプ
+    assert_eq!(
+        shell_parse("ls | wc"),
+        Ok(pipeline(vec![
+            (simple_command(("ls", 0..2), &[]), Some(Pipe::Pipe)),
+            (simple_command(("wc", 5..7), &[]), None),
+        ]))
+    );
+    assert_eq!(
+        shell_parse("ls|wc"),
+        Ok(pipeline(vec![
+            (simple_command(("ls", 0..2), &[]), Some(Pipe::Pipe)),
+            (simple_command(("wc", 3..5), &[]), None),
+        ]))
+    );
+    assert_eq!(
+        shell_parse("cat file | grep a | wc -l"),
+        Ok(pipeline(vec![
+            (
+                simple_command(("cat", 0..3), &[("file", 4..8)]),
+                Some(Pipe::Pipe)
+            ),
+            (
+                simple_command(("grep", 11..15), &[("a", 16..17)]),
+                Some(Pipe::Pipe)
+            ),
+            (simple_command(("wc", 20..22), &[("-l", 23..25)]), None),
+        ]))
+    );
+    assert_eq!(
+        shell_parse("echo \"a | b\" | cat"),
+        Ok(pipeline(vec![
+            (
+                simple_command(("echo", 0..4), &[("a | b", 5..12)]),
+                Some(Pipe::Pipe)
+            ),
+            (simple_command(("cat", 15..18), &[]), None),
+        ]))
+    );
+
+    // パイプとコメント
+    assert_eq!(
+        shell_parse("ls | wc # count"),
+        Ok(ShellCommand {
+            commands: vec![
+                (
+                    Statement::Command(simple_command(("ls", 0..2), &[])),
+                    Some(Pipe::Pipe)
+                ),
+                (Statement::Command(simple_command(("wc", 5..7), &[])), None),
+            ],
+            comment: Some(" count".to_string()),
+        })
+    );
+
+    // パイプの後にコマンドがない
+    assert_eq!(
+        shell_parse("ls |").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterPipe, 4)
+    );
+    assert_eq!(
+        shell_parse("ls | ").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterPipe, 5)
+    );
+    assert_eq!(
+        shell_parse("ls | # comment").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterPipe, 5)
+    );
+    assert_eq!(
+        shell_parse("ls | | wc").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterPipe, 5)
+    );
+    assert_eq!(
+        shell_parse("ls | \"wc").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoEndDoubleQuotation, 8)
+    );
+}
+#[test]
+fn split_test() {
+    let commands = |commands: Vec<(Command, Option<Pipe>)>| ShellCommand {
+        commands: commands
+            .into_iter()
+            .map(|(command, pipe)| (Statement::Command(command), pipe))
+            .collect(),
+        comment: None,
+    };
+
+    // 基本的な区切り
+    assert_eq!(
+        shell_parse("cd /tmp; ls"),
+        Ok(commands(vec![
+            (
+                simple_command(("cd", 0..2), &[("/tmp", 3..7)]),
+                Some(Pipe::Split)
+            ),
+            (simple_command(("ls", 9..11), &[]), None),
+        ]))
+    );
+    assert_eq!(
+        shell_parse("a;b ; c"),
+        Ok(commands(vec![
+            (simple_command(("a", 0..1), &[]), Some(Pipe::Split)),
+            (simple_command(("b", 2..3), &[]), Some(Pipe::Split)),
+            (simple_command(("c", 6..7), &[]), None),
+        ]))
+    );
+    assert_eq!(
+        shell_parse("echo \"a; b\"; ls"),
+        Ok(commands(vec![
+            (
+                simple_command(("echo", 0..4), &[("a; b", 5..11)]),
+                Some(Pipe::Split)
+            ),
+            (simple_command(("ls", 13..15), &[]), None),
+        ]))
+    );
+
+    // 末尾の区切り
+    assert_eq!(
+        shell_parse("ls;"),
+        Ok(commands(vec![(
+            simple_command(("ls", 0..2), &[]),
+            Some(Pipe::Split)
+        )]))
+    );
+    assert_eq!(
+        shell_parse("ls ; # comment"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(simple_command(("ls", 0..2), &[])),
+                Some(Pipe::Split)
+            )],
+            comment: Some(" comment".to_string()),
+        })
+    );
+
+    // パイプとの組み合わせ
+    assert_eq!(
+        shell_parse("ls | wc; pwd"),
+        Ok(commands(vec![
+            (simple_command(("ls", 0..2), &[]), Some(Pipe::Pipe)),
+            (simple_command(("wc", 5..7), &[]), Some(Pipe::Split)),
+            (simple_command(("pwd", 9..12), &[]), None),
+        ]))
+    );
+
+    // 不正な区切り
+    assert_eq!(
+        shell_parse("; ls").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::Other, 0)
+    );
+    assert_eq!(
+        shell_parse("ls;;").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::Other, 3)
+    );
+    assert_eq!(
+        shell_parse("ls; | wc").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::Other, 4)
+    );
+}
+
+#[test]
+fn redirect_test() {
+    let redirect = |fd: u32, target: RedirectTarget, span: Span| Spanned {
+        inner: Redirect { fd, target },
+        span,
+    };
+    let redirected = |command: Command, redirects| ShellCommand {
+        commands: vec![(
+            Statement::Command(Command {
+                redirects,
+                ..command
+            }),
+            None,
+        )],
+        comment: None,
+    };
+    use RedirectTarget::*;
+
+    // 基本的なリダイレクト
+    assert_eq!(
+        shell_parse("ls > out.txt"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(1, Write(literal(("out.txt", 5..12))), 3..12)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("ls >> out.txt"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(1, Append(literal(("out.txt", 6..13))), 3..13)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("wc < in.txt"),
+        Ok(redirected(
+            simple_command(("wc", 0..2), &[]),
+            vec![redirect(0, Read(literal(("in.txt", 5..11))), 3..11)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("ls 2> err.txt"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(2, Write(literal(("err.txt", 6..13))), 3..13)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("ls 2>> err.txt"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(2, Append(literal(("err.txt", 7..14))), 3..14)]
+        ))
+    );
+
+    // ファイルディスクリプタの複製
+    assert_eq!(
+        shell_parse("ls 2>&1"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(
+                2,
+                Duplicate(Spanned {
+                    inner: 1,
+                    span: 6..7
+                }),
+                3..7
+            )]
+        ))
+    );
+    assert_eq!(
+        shell_parse("ls >&2"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(
+                1,
+                Duplicate(Spanned {
+                    inner: 2,
+                    span: 5..6
+                }),
+                3..6
+            )]
+        ))
+    );
+
+    // 空白なし・引数との混在
+    assert_eq!(
+        shell_parse("ls>out"),
+        Ok(redirected(
+            simple_command(("ls", 0..2), &[]),
+            vec![redirect(1, Write(literal(("out", 3..6))), 2..6)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("sort -r < in > out 2>&1"),
+        Ok(redirected(
+            simple_command(("sort", 0..4), &[("-r", 5..7)]),
+            vec![
+                redirect(0, Read(literal(("in", 10..12))), 8..12),
+                redirect(1, Write(literal(("out", 15..18))), 13..18),
+                redirect(
+                    2,
+                    Duplicate(Spanned {
+                        inner: 1,
+                        span: 22..23
+                    }),
+                    19..23
+                ),
+            ]
+        ))
+    );
+    assert_eq!(
+        shell_parse("echo a > out b"),
+        Ok(redirected(
+            simple_command(("echo", 0..4), &[("a", 5..6), ("b", 13..14)]),
+            vec![redirect(1, Write(literal(("out", 9..12))), 7..12)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("echo 2 a2>out"),
+        Ok(redirected(
+            simple_command(("echo", 0..4), &[("2", 5..6), ("a2", 7..9)]),
+            vec![redirect(1, Write(literal(("out", 10..13))), 9..13)]
+        ))
+    );
+    assert_eq!(
+        shell_parse("echo hi > \"my file\""),
+        Ok(redirected(
+            simple_command(("echo", 0..4), &[("hi", 5..7)]),
+            vec![redirect(1, Write(literal(("my file", 10..19))), 8..19)]
+        ))
+    );
+
+    // パイプとの組み合わせ
+    assert_eq!(
+        shell_parse("ls 2>&1 | wc > out"),
+        Ok(ShellCommand {
+            commands: vec![
+                (
+                    Statement::Command(Command {
+                        redirects: vec![redirect(
+                            2,
+                            Duplicate(Spanned {
+                                inner: 1,
+                                span: 6..7
+                            }),
+                            3..7
+                        )],
+                        ..simple_command(("ls", 0..2), &[])
+                    }),
+                    Some(Pipe::Pipe)
+                ),
+                (
+                    Statement::Command(Command {
+                        redirects: vec![redirect(
+                            1,
+                            Write(literal(("out", 15..18))),
+                            13..18
+                        )],
+                        ..simple_command(("wc", 10..12), &[])
+                    }),
+                    None
+                ),
+            ],
+            comment: None,
+        })
+    );
+
+    // リダイレクト先がない
+    assert_eq!(
+        shell_parse("ls >").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 4)
+    );
+    assert_eq!(
+        shell_parse("ls >  ").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 6)
+    );
+    assert_eq!(
+        shell_parse("ls < | wc").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 5)
+    );
+    assert_eq!(
+        shell_parse("ls > # comment").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 5)
+    );
+    assert_eq!(
+        shell_parse("ls 2>; pwd").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 5)
+    );
+
+    // 不正なファイルディスクリプタ
+    assert_eq!(
+        shell_parse("ls 3> out").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::InvalidFileDescriptor, 3)
+    );
+    assert_eq!(
+        shell_parse("ls 2>&3").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::InvalidFileDescriptor, 6)
+    );
+    assert_eq!(
+        shell_parse("ls >&").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::InvalidFileDescriptor, 5)
+    );
+}
+
+#[test]
+fn var_operator_test() {
+    let postfix = |word: Spanned<Word>, op: Postfix, span: Span| Spanned {
+        inner: Word::Postfix(Box::new(word), op),
+        span,
+    };
+    let unwrap_or =
+        |word: Spanned<Word>, default: Spanned<Word>, span: Span| Spanned {
+            inner: Word::UnwrapOr(Box::new(word), Box::new(default)),
+            span,
+        };
+
+    // 後置演算子
+    assert_eq!(
+        word_parse("$HOME?"),
+        Ok(postfix(env_var(("HOME", 0..5)), Postfix::IsSome, 0..6))
+    );
+    assert_eq!(
+        word_parse("$PATH!"),
+        Ok(postfix(env_var(("PATH", 0..5)), Postfix::Unwrap, 0..6))
+    );
+    assert_eq!(
+        word_parse("%name@"),
+        Ok(postfix(shell_var(("name", 0..5)), Postfix::Len, 0..6))
+    );
+    assert_eq!(
+        word_parse("$PATH!@"),
+        Ok(postfix(
+            postfix(env_var(("PATH", 0..5)), Postfix::Unwrap, 0..6),
+            Postfix::Len,
+            0..7
+        ))
+    );
+    assert_eq!(
+        word_parse("$!!"),
+        Ok(postfix(
+            special_var(SpecialVar::BackgroundPid, 0..2),
+            Postfix::Unwrap,
+            0..3
+        ))
+    );
+    assert_eq!(
+        word_peek("$HOME?/bin"),
+        Ok((
+            "/bin",
+            postfix(env_var(("HOME", 0..5)), Postfix::IsSome, 0..6)
+        ))
+    );
+
+    // デフォルト値
+    assert_eq!(
+        word_parse("$EDITOR ^ \"vim\""),
+        Ok(unwrap_or(
+            env_var(("EDITOR", 0..7)),
+            literal(("vim", 10..15)),
+            0..15
+        ))
+    );
+    assert_eq!(
+        word_parse("$EDITOR^vim"),
+        Ok(unwrap_or(
+            env_var(("EDITOR", 0..7)),
+            literal(("vim", 8..11)),
+            0..11
+        ))
+    );
+    assert_eq!(
+        word_parse("$A ^ $B ^ c"),
+        Ok(unwrap_or(
+            env_var(("A", 0..2)),
+            unwrap_or(env_var(("B", 5..7)), literal(("c", 10..11)), 5..11),
+            0..11
+        ))
+    );
+    assert_eq!(
+        word_parse("%opt ^ $B!"),
+        Ok(unwrap_or(
+            shell_var(("opt", 0..4)),
+            postfix(env_var(("B", 7..9)), Postfix::Unwrap, 7..10),
+            0..10
+        ))
+    );
+
+    // コマンドの引数
+    assert_eq!(
+        shell_parse("echo $EDITOR ^ vim file"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(Command {
+                    env: vec![],
+                    name: literal(("echo", 0..4)),
+                    args: vec![
+                        unwrap_or(
+                            env_var(("EDITOR", 5..12)),
+                            literal(("vim", 15..18)),
+                            5..18
+                        ),
+                        literal(("file", 19..23)),
+                    ],
+                    redirects: vec![],
+                }),
+                None
+            )],
+            comment: None
+        })
+    );
+    assert_eq!(
+        shell_parse("echo a ^ b"),
+        Ok(shell(
+            ("echo", 0..4),
+            &[("a", 5..6), ("^", 7..8), ("b", 9..10)],
+            None
+        ))
+    );
+
+    // デフォルト値がない
+    assert_eq!(
+        word_parse("$A ^").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoDefaultValue, 4)
+    );
+    assert_eq!(
+        shell_parse("echo $A ^ # comment").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoDefaultValue, 10)
+    );
+}
+#[test]
+fn assign_test() {
+    let assign = |target: AssignTarget,
+                  target_span: Span,
+                  ty: Option<(Type, Span)>,
+                  value: AssignValue| ShellCommand {
+        commands: vec![(
+            Statement::Assign(Assign {
+                target: spanned((target, target_span)),
+                ty: ty.map(spanned),
+                value,
+            }),
+            None,
+        )],
+        comment: None,
+    };
+    let env = |name: &str| AssignTarget::EnvVar(name.to_string());
+    let var = |name: &str| AssignTarget::ShellVar(name.to_string());
+    let none = |span: Span| AssignValue::None(Spanned { inner: (), span });
+
+    // 環境変数
+    assert_eq!(
+        shell_parse("$EDITOR = \"vim\""),
+        Ok(assign(
+            env("EDITOR"),
+            0..7,
+            None,
+            AssignValue::Word(literal(("vim", 10..15)))
+        ))
+    );
+    assert_eq!(
+        shell_parse("$EDITOR=vim"),
+        Ok(assign(
+            env("EDITOR"),
+            0..7,
+            None,
+            AssignValue::Word(literal(("vim", 8..11)))
+        ))
+    );
+    assert_eq!(
+        shell_parse("$EDITOR = none"),
+        Ok(assign(env("EDITOR"), 0..7, None, none(10..14)))
+    );
+    // noneで始まる単語は値
+    assert_eq!(
+        shell_parse("$A = none.txt"),
+        Ok(assign(
+            env("A"),
+            0..2,
+            None,
+            AssignValue::Word(literal(("none.txt", 5..13)))
+        ))
+    );
+
+    // シェル変数
+    assert_eq!(
+        shell_parse("%name = $USER ^ guest"),
+        Ok(assign(
+            var("name"),
+            0..5,
+            None,
+            AssignValue::Word(Spanned {
+                inner: Word::UnwrapOr(
+                    Box::new(env_var(("USER", 8..13))),
+                    Box::new(literal(("guest", 16..21)))
+                ),
+                span: 8..21
+            })
+        ))
+    );
+    assert_eq!(
+        shell_parse("%count: int = 1"),
+        Ok(assign(
+            var("count"),
+            0..6,
+            Some((Type::Int, 8..11)),
+            AssignValue::Word(literal(("1", 14..15)))
+        ))
+    );
+    assert_eq!(
+        shell_parse("%files:array<path>=none"),
+        Ok(assign(
+            var("files"),
+            0..6,
+            Some((Type::Array(Box::new(Type::Path)), 7..18)),
+            none(19..23)
+        ))
+    );
+    assert_eq!(
+        shell_parse("%x: option< array<string> > = none"),
+        Ok(assign(
+            var("x"),
+            0..2,
+            Some((
+                Type::Option(Box::new(Type::Array(Box::new(Type::String)))),
+                4..27
+            )),
+            none(30..34)
+        ))
+    );
+
+    // ;やパイプで区切られる
+    let ShellCommand { commands, .. } = shell_parse("%a = 1; echo %a").unwrap();
+    assert!(matches!(
+        &commands[..],
+        [
+            (Statement::Assign(_), Some(Pipe::Split)),
+            (Statement::Command(_), None)
+        ]
+    ));
+
+    // 代入でなければコマンド
+    assert_eq!(
+        shell_parse("$EDITOR file"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(Command {
+                    env: vec![],
+                    name: env_var(("EDITOR", 0..7)),
+                    args: vec![literal(("file", 8..12))],
+                    redirects: vec![],
+                }),
+                None
+            )],
+            comment: None
+        })
+    );
+    assert!(matches!(
+        &shell_parse("$? == 0").unwrap().commands[..],
+        [(Statement::Command(_), None)]
+    ));
+
+    // エラー
+    assert_eq!(
+        shell_parse("%a =").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoAssignValue, 4)
+    );
+    assert_eq!(
+        shell_parse("$A = # comment").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoAssignValue, 5)
+    );
+    assert_eq!(
+        shell_parse("%a: integer = 1").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::UnknownType("integer".to_string()), 4)
+    );
+    assert_eq!(
+        shell_parse("%a: array = 1").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoTypeArgument, 9)
+    );
+    assert_eq!(
+        shell_parse("%a: option<int = 1").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoEndAngleBracket, 14)
+    );
+}
+#[test]
+fn temp_env_test() {
+    let temp_env = |name: &str, value: Option<Spanned<Word>>| TempEnv {
+        name: name.to_string(),
+        value,
+    };
+
+    assert_eq!(
+        shell_parse("$LANG := C sort file"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(Command {
+                    env: vec![temp_env("LANG", Some(literal(("C", 9..10))))],
+                    name: literal(("sort", 11..15)),
+                    args: vec![literal(("file", 16..20))],
+                    redirects: vec![],
+                }),
+                None
+            )],
+            comment: None
+        })
+    );
+    assert_eq!(
+        shell_parse("$A:=\"1\" $B := none $C ^ ls"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(Command {
+                    env: vec![
+                        temp_env("A", Some(literal(("1", 4..7)))),
+                        temp_env("B", None),
+                    ],
+                    name: Spanned {
+                        inner: Word::UnwrapOr(
+                            Box::new(env_var(("C", 19..21))),
+                            Box::new(literal(("ls", 24..26)))
+                        ),
+                        span: 19..26
+                    },
+                    args: vec![],
+                    redirects: vec![],
+                }),
+                None
+            )],
+            comment: None
+        })
+    );
+
+    // コマンドが必要
+    assert_eq!(
+        shell_parse("$A := 1").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterEnv, 7)
+    );
+    assert_eq!(
+        shell_parse("$A := 1 | cat").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCommandAfterEnv, 8)
+    );
+    assert_eq!(
+        shell_parse("$A :=").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoAssignValue, 5)
+    );
+}
+#[test]
+fn expr_test() {
+    let expr_parse = |input: &str| match word_parse(input) {
+        Ok(Spanned {
+            inner: Word::Expr(expr),
+            ..
+        }) => Ok(*expr),
+        Ok(word) => panic!("式ではありません : {word}"),
+        Err(e) => Err(e.into_inner()),
+    };
+    let expr = |inner: Expr, span: Span| Spanned { inner, span };
+    let int = |int: i64, span: Span| expr(Expr::Int(int), span);
+    let binary = |left: Spanned<Expr>, op: BinaryOp, right: Spanned<Expr>| {
+        let span = left.span.start..right.span.end;
+        expr(Expr::Binary(Box::new(left), op, Box::new(right)), span)
+    };
+
+    // リテラル
+    assert_eq!(expr_parse("(42)"), Ok(int(42, 1..3)));
+    assert_eq!(
+        expr_parse("( 4.2 )"),
+        Ok(expr(Expr::Float(Float(4.2)), 2..5))
+    );
+    assert_eq!(expr_parse("(true)"), Ok(expr(Expr::Bool(true), 1..5)));
+    assert_eq!(
+        expr_parse("(\"hello\")"),
+        Ok(expr(Expr::Word(Word::Literal("hello".to_string())), 1..8))
+    );
+    assert_eq!(
+        expr_parse("(p\"~\")"),
+        Ok(expr(Expr::Word(Word::PathLiteral("~".to_string())), 1..5))
+    );
+    assert_eq!(
+        expr_parse("(%x)"),
+        Ok(expr(Expr::Word(Word::ShellVar("x".to_string())), 1..3))
+    );
+
+    // 優先順位と結合
+    assert_eq!(
+        expr_parse("(1 + 2 * 3)"),
+        Ok(binary(
+            int(1, 1..2),
+            BinaryOp::Add,
+            binary(int(2, 5..6), BinaryOp::Mul, int(3, 9..10))
+        ))
+    );
+    assert_eq!(
+        expr_parse("(1-2-3)"),
+        Ok(binary(
+            binary(int(1, 1..2), BinaryOp::Sub, int(2, 3..4)),
+            BinaryOp::Sub,
+            int(3, 5..6)
+        ))
+    );
+    assert_eq!(
+        expr_parse("((1 + 2) % 3)"),
+        Ok(binary(
+            // 括弧を含めた範囲になる
+            expr(
+                Expr::Binary(
+                    Box::new(int(1, 2..3)),
+                    BinaryOp::Add,
+                    Box::new(int(2, 6..7))
+                ),
+                1..8
+            ),
+            BinaryOp::Rem,
+            int(3, 11..12)
+        ))
+    );
+    assert_eq!(
+        expr_parse("(1 < 2 || 3 >= 4 && true)"),
+        Ok(binary(
+            binary(int(1, 1..2), BinaryOp::Lt, int(2, 5..6)),
+            BinaryOp::Or,
+            binary(
+                binary(int(3, 10..11), BinaryOp::Ge, int(4, 15..16)),
+                BinaryOp::And,
+                expr(Expr::Bool(true), 20..24)
+            )
+        ))
+    );
+    assert_eq!(
+        expr_parse("(-%a % %b)"),
+        Ok(binary(
+            expr(
+                Expr::Unary(
+                    UnaryOp::Neg,
+                    Box::new(expr(
+                        Expr::Word(Word::ShellVar("a".to_string())),
+                        2..4
+                    ))
+                ),
+                1..4
+            ),
+            BinaryOp::Rem,
+            expr(Expr::Word(Word::ShellVar("b".to_string())), 7..9)
+        ))
+    );
+
+    // 後置演算子と!=
+    assert_eq!(
+        expr_parse("($A! != $B ^ \"b\")"),
+        Ok(binary(
+            expr(
+                Expr::Postfix(
+                    Box::new(expr(
+                        Expr::Word(Word::EnvVar("A".to_string())),
+                        1..3
+                    )),
+                    Postfix::Unwrap
+                ),
+                1..4
+            ),
+            BinaryOp::Ne,
+            expr(
+                Expr::UnwrapOr(
+                    Box::new(expr(
+                        Expr::Word(Word::EnvVar("B".to_string())),
+                        8..10
+                    )),
+                    Box::new(expr(
+                        Expr::Word(Word::Literal("b".to_string())),
+                        13..16
+                    ))
+                ),
+                8..16
+            )
+        ))
+    );
+
+    // 複数行
+    assert_eq!(
+        expr_parse("(1\n+ 2)"),
+        Ok(binary(int(1, 1..2), BinaryOp::Add, int(2, 5..6)))
+    );
+
+    // コマンドの引数
+    assert_eq!(
+        shell_parse("echo (1) a"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Command(Command {
+                    env: vec![],
+                    name: literal(("echo", 0..4)),
+                    args: vec![
+                        Spanned {
+                            inner: Word::Expr(Box::new(int(1, 6..7))),
+                            span: 5..8
+                        },
+                        literal(("a", 9..10)),
+                    ],
+                    redirects: vec![],
+                }),
+                None
+            )],
+            comment: None
+        })
+    );
+
+    // エラー
+    assert_eq!(
+        expr_parse("()"),
+        Err(parse_error(ParseErrorKind::NoExpr, 1))
+    );
+    assert_eq!(
+        expr_parse("(1 +)"),
+        Err(parse_error(ParseErrorKind::NoExpr, 4))
+    );
+    assert_eq!(
+        expr_parse("(1 2)"),
+        Err(parse_error(ParseErrorKind::NoEndParen, 3))
+    );
+    assert_eq!(
+        expr_parse("(1 < 2 < 3)"),
+        Err(parse_error(ParseErrorKind::NoEndParen, 7))
+    );
+    assert_eq!(
+        expr_parse("(hello)"),
+        Err(parse_error(ParseErrorKind::UnquotedStringInExpr, 1))
+    );
+    assert_eq!(
+        expr_parse("(12ab)"),
+        Err(parse_error(ParseErrorKind::InvalidNumber, 1))
+    );
+    assert_eq!(
+        expr_parse("(99999999999999999999)"),
+        Err(parse_error(ParseErrorKind::InvalidNumber, 1))
+    );
+}
+#[test]
+fn as_test() {
+    let expr_parse = |input: &str| match word_parse(input) {
+        Ok(Spanned {
+            inner: Word::Expr(expr),
+            ..
+        }) => Ok(*expr),
+        Ok(word) => panic!("式ではありません : {word}"),
+        Err(e) => Err(e.into_inner()),
+    };
+    let expr = |inner: Expr, span: Span| Spanned { inner, span };
+    let cast = |operand: Spanned<Expr>, ty: Type, end: usize| {
+        let span = operand.span.start..end;
+        expr(Expr::As(Box::new(operand), ty), span)
+    };
+
+    assert_eq!(
+        expr_parse("(42 as string)"),
+        Ok(cast(expr(Expr::Int(42), 1..3), Type::String, 13))
+    );
+    // 連続した変換
+    assert_eq!(
+        expr_parse("(%x as string as int)"),
+        Ok(cast(
+            cast(
+                expr(Expr::Word(Word::ShellVar("x".to_string())), 1..3),
+                Type::String,
+                13
+            ),
+            Type::Int,
+            20
+        ))
+    );
+    // asは単項演算子より弱く、二項演算子より強い
+    assert_eq!(
+        expr_parse("(-1 as float * 2.0)"),
+        Ok(expr(
+            Expr::Binary(
+                Box::new(cast(
+                    expr(
+                        Expr::Unary(
+                            UnaryOp::Neg,
+                            Box::new(expr(Expr::Int(1), 2..3))
+                        ),
+                        1..3
+                    ),
+                    Type::Float,
+                    12
+                )),
+                BinaryOp::Mul,
+                Box::new(expr(Expr::Float(Float(2.0)), 15..18))
+            ),
+            1..18
+        ))
+    );
+    assert_eq!(
+        expr_parse("(%a as option<int>)"),
+        Ok(cast(
+            expr(Expr::Word(Word::ShellVar("a".to_string())), 1..3),
+            Type::Option(Box::new(Type::Int)),
+            18
+        ))
+    );
+
+    // エラー
+    assert_eq!(
+        expr_parse("(1 as number)"),
+        Err(parse_error(
+            ParseErrorKind::UnknownType("number".to_string()),
+            6
+        ))
+    );
+    assert_eq!(
+        expr_parse("(1 asint)"),
+        Err(parse_error(ParseErrorKind::NoEndParen, 3))
+    );
+}
+
+#[test]
+fn array_test() {
+    let expr_parse = |input: &str| match word_parse(input) {
+        Ok(Spanned {
+            inner: Word::Expr(expr),
+            ..
+        }) => Ok(*expr),
+        Ok(word) => panic!("式ではありません : {word}"),
+        Err(e) => Err(e.into_inner()),
+    };
+    let expr = |inner: Expr, span: Span| Spanned { inner, span };
+    let int = |n: i64, span: Span| expr(Expr::Int(n), span);
+    let index = |index: Spanned<Expr>| Postfix::Index(Box::new(index));
+
+    // 配列リテラル
+    assert_eq!(
+        expr_parse("([1, 2, 3])"),
+        Ok(expr(
+            Expr::Array(vec![int(1, 2..3), int(2, 5..6), int(3, 8..9)]),
+            1..10
+        ))
+    );
+    // 末尾のカンマ
+    assert_eq!(
+        expr_parse("([ 1, 2, ])"),
+        Ok(expr(Expr::Array(vec![int(1, 3..4), int(2, 6..7)]), 1..10))
+    );
+    assert_eq!(expr_parse("([])"), Ok(expr(Expr::Array(vec![]), 1..3)));
+
+    // インデックス
+    assert_eq!(
+        word_parse("%a[0]").map_err(|e| e.into_inner()),
+        Ok(Spanned {
+            inner: Word::Postfix(
+                Box::new(shell_var(("a", 0..2))),
+                index(int(0, 3..4))
+            ),
+            span: 0..5
+        })
+    );
+    assert_eq!(
+        expr_parse("(%a[-1])"),
+        Ok(expr(
+            Expr::Postfix(
+                Box::new(expr(
+                    Expr::Word(Word::ShellVar("a".to_string())),
+                    1..3
+                )),
+                index(expr(
+                    Expr::Unary(UnaryOp::Neg, Box::new(int(1, 5..6))),
+                    4..6
+                ))
+            ),
+            1..7
+        ))
+    );
+
+    // 要素への代入と配列の代入
+    assert_eq!(
+        shell_parse("%a[1] = x"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Assign(Assign {
+                    target: Spanned {
+                        inner: AssignTarget::Element(
+                            "a".to_string(),
+                            Box::new(int(1, 3..4))
+                        ),
+                        span: 0..5
+                    },
+                    ty: None,
+                    value: AssignValue::Word(literal(("x", 8..9))),
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    assert_eq!(
+        shell_parse("%a = [\"x\"]"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Assign(Assign {
+                    target: Spanned {
+                        inner: AssignTarget::ShellVar("a".to_string()),
+                        span: 0..2
+                    },
+                    ty: None,
+                    value: AssignValue::Word(Spanned {
+                        inner: Word::Expr(Box::new(expr(
+                            Expr::Array(vec![expr(
+                                Expr::Word(Word::Literal("x".to_string())),
+                                6..9
+                            )]),
+                            5..10
+                        ))),
+                        span: 5..10
+                    }),
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    // コマンドの引数では配列リテラルにならない
+    assert_eq!(
+        shell_parse("[ -f x ]"),
+        Ok(shell(
+            ("[", 0..1),
+            &[("-f", 2..4), ("x", 5..6), ("]", 7..8)],
+            None
+        ))
+    );
+    assert_eq!(word_parse("[0-9]"), Ok(literal(("[0-9]", 0..5))));
+    assert_eq!(word_parse("[:upper:]"), Ok(literal(("[:upper:]", 0..9))));
+
+    // エラー
+    assert_eq!(
+        expr_parse("([1, 2)"),
+        Err(parse_error(ParseErrorKind::NoEndBracket, 6))
+    );
+    assert_eq!(
+        word_parse("%a[0").map_err(|e| e.into_inner()),
+        Err(parse_error(ParseErrorKind::NoEndBracket, 4))
+    );
+}
+
+#[test]
+fn len_test() {
+    let len = |word: Spanned<Word>, end: usize| Spanned {
+        span: word.span.start..end,
+        inner: Word::Postfix(Box::new(word), Postfix::Len),
+    };
+
+    // リテラルや式にも使える
+    assert_eq!(
+        word_parse("\"hello\"@").map_err(|e| e.into_inner()),
+        Ok(len(literal(("hello", 0..7)), 8))
+    );
+    assert_eq!(
+        word_parse("p\"~/file\"@").map_err(|e| e.into_inner()),
+        Ok(len(path_literal(("~/file", 0..9)), 10))
+    );
+    assert_eq!(
+        word_parse("(\"ab\" + \"cd\")@").map_err(|e| e.into_inner()),
+        Ok(len(
+            Spanned {
+                inner: Word::Expr(Box::new(Spanned {
+                    inner: Expr::Binary(
+                        Box::new(Spanned {
+                            inner: Expr::Word(Word::Literal("ab".to_string())),
+                            span: 1..5
+                        }),
+                        BinaryOp::Add,
+                        Box::new(Spanned {
+                            inner: Expr::Word(Word::Literal("cd".to_string())),
+                            span: 8..12
+                        })
+                    ),
+                    span: 1..12
+                })),
+                span: 0..13
+            },
+            14
+        ))
+    );
+    // $@はシェル名で、続く@が長さ
+    assert_eq!(
+        word_parse("$@").map_err(|e| e.into_inner()),
+        Ok(special_var(SpecialVar::ShellName, 0..2))
+    );
+    assert_eq!(
+        word_parse("$@@").map_err(|e| e.into_inner()),
+        Ok(len(special_var(SpecialVar::ShellName, 0..2), 3))
+    );
+    // クォートなしの文字列では@も文字列の一部
+    assert_eq!(
+        word_parse("user@host").map_err(|e| e.into_inner()),
+        Ok(literal(("user@host", 0..9)))
+    );
+}
+
+#[test]
+fn option_test() {
+    let expr_parse = |input: &str| match word_parse(input) {
+        Ok(Spanned {
+            inner: Word::Expr(expr),
+            ..
+        }) => Ok(*expr),
+        Ok(word) => panic!("式ではありません : {word}"),
+        Err(e) => Err(e.into_inner()),
+    };
+    let expr = |inner: Expr, span: Span| Spanned { inner, span };
+
+    assert_eq!(
+        expr_parse("(some(42))"),
+        Ok(expr(Expr::Some(Box::new(expr(Expr::Int(42), 6..8))), 1..9))
+    );
+    assert_eq!(
+        expr_parse("(some( 'a' ) == none)"),
+        Ok(expr(
+            Expr::Binary(
+                Box::new(expr(
+                    Expr::Some(Box::new(expr(
+                        Expr::Word(Word::Literal("a".to_string())),
+                        7..10
+                    ))),
+                    1..12
+                )),
+                BinaryOp::Eq,
+                Box::new(expr(Expr::None, 16..20))
+            ),
+            1..20
+        ))
+    );
+    // 代入する値では括弧を省略できる
+    assert_eq!(
+        shell_parse("%o = some(1)"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Assign(Assign {
+                    target: Spanned {
+                        inner: AssignTarget::ShellVar("o".to_string()),
+                        span: 0..2
+                    },
+                    ty: None,
+                    value: AssignValue::Word(Spanned {
+                        inner: Word::Expr(Box::new(expr(
+                            Expr::Some(Box::new(expr(Expr::Int(1), 10..11))),
+                            5..12
+                        ))),
+                        span: 5..12
+                    }),
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    // 括弧が続かなければただの文字列
+    assert_eq!(
+        shell_parse("%o = something"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Assign(Assign {
+                    target: Spanned {
+                        inner: AssignTarget::ShellVar("o".to_string()),
+                        span: 0..2
+                    },
+                    ty: None,
+                    value: AssignValue::Word(literal(("something", 5..14))),
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+
+    // エラー
+    assert_eq!(
+        expr_parse("(some 1)"),
+        Err(parse_error(ParseErrorKind::NoSomeValue, 5))
+    );
+    assert_eq!(
+        expr_parse("(some(1)"),
+        Err(parse_error(ParseErrorKind::NoEndParen, 8))
+    );
+}
+
+#[test]
+fn incomplete_test() {
+    let incomplete = |input: &str| match shell_parse(input) {
+        Ok(_) => false,
+        Err(e) => e.inner().is_incomplete(input),
+    };
+
+    // 閉じていない括弧・クォート・コメントは次の行に続く
+    assert!(incomplete("echo (1 +"));
+    assert!(incomplete("echo (1 + 2"));
+    assert!(incomplete("%a = [1,\n2"));
+    assert!(incomplete("echo \"hello"));
+    assert!(incomplete("echo 'hello"));
+    assert!(incomplete("#| comment"));
+    assert!(incomplete("echo #| outer #| inner |#"));
+    assert!(incomplete("if x {"));
+    assert!(incomplete("if x {\n  echo a"));
+    assert!(incomplete("if x { echo a # }"));
+    assert!(incomplete("fn f(%a: int,"));
+    assert!(incomplete("fn f() {"));
+    assert!(incomplete("match %x {\n  1 => echo one"));
+    assert!(incomplete("{ echo a"));
+
+    // 途中で間違っている場合は続けても直らない
+    assert!(!incomplete("echo hello"));
+    assert!(!incomplete("echo (1 2)"));
+    assert!(!incomplete("echo (1 + )"));
+    assert!(!incomplete("echo ls |"));
+    assert!(!incomplete("if x { echo a )"));
+    assert!(!incomplete("if x"));
+}
+
+#[test]
+fn if_test() {
+    let block = |command, args| Block {
+        commands: vec![(
+            Statement::Command(simple_command(command, args)),
+            None,
+        )],
+    };
+    let if_statement = |if_| ShellCommand {
+        commands: vec![(Statement::If(if_), None)],
+        comment: None,
+    };
+
+    // 変数や式はbool型の条件
+    assert_eq!(
+        shell_parse("if $HOME? { echo a }"),
+        Ok(if_statement(If {
+            branches: vec![(
+                Condition::Expr(Spanned {
+                    inner: Word::Postfix(
+                        Box::new(env_var(("HOME", 3..8))),
+                        Postfix::IsSome
+                    ),
+                    span: 3..9
+                }),
+                block(("echo", 12..16), &[("a", 17..18)])
+            )],
+            else_block: None,
+        }))
+    );
+    // それ以外はコマンドの終了ステータス
+    assert_eq!(
+        shell_parse("if test -d x { echo a } else { echo b }"),
+        Ok(if_statement(If {
+            branches: vec![(
+                Condition::Command(vec![Statement::Command(simple_command(
+                    ("test", 3..7),
+                    &[("-d", 8..10), ("x", 11..12)]
+                ))]),
+                block(("echo", 15..19), &[("a", 20..21)])
+            )],
+            else_block: Some(block(("echo", 31..35), &[("b", 36..37)])),
+        }))
+    );
+    assert_eq!(
+        shell_parse("if true{}else if false{}else{}"),
+        Ok(if_statement(If {
+            branches: vec![
+                (
+                    Condition::Command(vec![Statement::Command(
+                        simple_command(("true", 3..7), &[])
+                    )]),
+                    Block::default()
+                ),
+                (
+                    Condition::Command(vec![Statement::Command(
+                        simple_command(("false", 17..22), &[])
+                    )]),
+                    Block::default()
+                ),
+            ],
+            else_block: Some(Block::default()),
+        }))
+    );
+    // ブロックの中では改行も文の区切り
+    let Ok(ShellCommand { commands, .. }) = shell_parse(
+        "if (%a == 1) {\n  # comment\n  echo a\n  echo b # comment\n\n} else {\n}",
+    )
+    else {
+        panic!("複数行のifを解析できませんでした");
+    };
+    let [
+        (
+            Statement::If(If {
+                branches,
+                else_block,
+            }),
+            None,
+        ),
+    ] = commands.as_slice()
+    else {
+        panic!("ifとして解析されませんでした");
+    };
+    assert_eq!(branches.len(), 1);
+    assert!(matches!(branches[0].0, Condition::Expr(_)));
+    assert_eq!(
+        branches[0].1.commands,
+        vec![
+            (
+                Statement::Command(simple_command(
+                    ("echo", 29..33),
+                    &[("a", 34..35)]
+                )),
+                Some(Pipe::Split)
+            ),
+            (
+                Statement::Command(simple_command(
+                    ("echo", 38..42),
+                    &[("b", 43..44)]
+                )),
+                Some(Pipe::Split)
+            ),
+        ]
+    );
+    assert_eq!(else_block, &Some(Block::default()));
+    // ifで始まる名前のコマンド
+    assert_eq!(
+        shell_parse("ifconfig"),
+        Ok(shell(("ifconfig", 0..8), &[], None))
+    );
+
+    // エラー
+    assert_eq!(
+        shell_parse("if { echo a }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCondition, 3)
+    );
+    assert_eq!(
+        shell_parse("if x y").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoBlock, 6)
+    );
+    assert_eq!(
+        shell_parse("if x { echo a } else")
+            .unwrap_err()
+            .into_inner(),
+        parse_error(ParseErrorKind::NoBlock, 20)
+    );
+    assert_eq!(
+        shell_parse("if x { echo a )").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoEndBrace, 14)
+    );
+}
+
+#[test]
+fn newline_test() {
+    // 改行は;と同じ区切り
+    assert_eq!(
+        shell_parse("echo a\necho b"),
+        Ok(ShellCommand {
+            commands: vec![
+                (
+                    Statement::Command(simple_command(
+                        ("echo", 0..4),
+                        &[("a", 5..6)]
+                    )),
+                    Some(Pipe::Split)
+                ),
+                (
+                    Statement::Command(simple_command(
+                        ("echo", 7..11),
+                        &[("b", 12..13)]
+                    )),
+                    None
+                ),
+            ],
+            comment: None,
+        })
+    );
+    // 括弧の中では改行は空白と同じ
+    assert_eq!(
+        shell_parse("echo (1 +\n2)").map(|cmd| cmd.commands.len()),
+        Ok(1)
+    );
+}
+
+#[test]
+fn loop_test() {
+    let echo = |span: Span, arg: SpannedInput| Block {
+        commands: vec![(
+            Statement::Command(Command {
+                env: vec![],
+                name: literal(("echo", span)),
+                args: vec![shell_var(arg)],
+                redirects: vec![],
+            }),
+            None,
+        )],
+    };
+    let loop_statement = |loop_| ShellCommand {
+        commands: vec![(Statement::Loop(loop_), None)],
+        comment: None,
+    };
+    let int = |int, span| Spanned {
+        inner: Expr::Int(int),
+        span,
+    };
+
+    assert_eq!(
+        shell_parse("for %x in %list { echo %x }"),
+        Ok(loop_statement(Loop {
+            label: None,
+            kind: LoopKind::For {
+                var: Spanned {
+                    inner: "x".to_string(),
+                    span: 4..6
+                },
+                iter: ForIter::Word(shell_var(("list", 10..15))),
+            },
+            block: echo(18..22, ("x", 23..25)),
+        }))
+    );
+    // 整数の範囲
+    assert_eq!(
+        shell_parse("for %i in 0..=%n { echo %i }"),
+        Ok(loop_statement(Loop {
+            label: None,
+            kind: LoopKind::For {
+                var: Spanned {
+                    inner: "i".to_string(),
+                    span: 4..6
+                },
+                iter: ForIter::Range {
+                    start: int(0, 10..11),
+                    end: Spanned {
+                        inner: Expr::Word(Word::ShellVar("n".to_string())),
+                        span: 14..16
+                    },
+                    inclusive: true,
+                },
+            },
+            block: echo(19..23, ("i", 24..26)),
+        }))
+    );
+    assert!(matches!(
+        shell_parse("for %i in -1..(%n - 1) { }").unwrap().commands[0].0,
+        Statement::Loop(Loop {
+            kind: LoopKind::For {
+                iter: ForIter::Range {
+                    inclusive: false,
+                    ..
+                },
+                ..
+            },
+            ..
+        })
+    ));
+    // 配列リテラルとパスのglobは括弧なしで書ける
+    assert!(matches!(
+        shell_parse("for %x in [1, 2] { }").unwrap().commands[0].0,
+        Statement::Loop(Loop {
+            kind: LoopKind::For {
+                iter: ForIter::Word(Spanned {
+                    inner: Word::Expr(_),
+                    ..
+                }),
+                ..
+            },
+            ..
+        })
+    ));
+    assert!(matches!(
+        shell_parse("for %f in p\"*.rs\" { }").unwrap().commands[0].0,
+        Statement::Loop(Loop {
+            kind: LoopKind::For {
+                iter: ForIter::Word(Spanned {
+                    inner: Word::PathLiteral(_),
+                    ..
+                }),
+                ..
+            },
+            ..
+        })
+    ));
+
+    // ラベルとbreak・continue
+    assert_eq!(
+        shell_parse("'outer: while %a { break 'outer; continue }"),
+        Ok(loop_statement(Loop {
+            label: Some("outer".to_string()),
+            kind: LoopKind::While(Condition::Expr(shell_var(("a", 14..16)))),
+            block: Block {
+                commands: vec![
+                    (
+                        Statement::Break(Spanned {
+                            inner: Some("outer".to_string()),
+                            span: 19..31
+                        }),
+                        Some(Pipe::Split)
+                    ),
+                    (
+                        Statement::Continue(Spanned {
+                            inner: None,
+                            span: 33..41
+                        }),
+                        None
+                    ),
+                ],
+            },
+        }))
+    );
+    // ループが続かなければただの文字列
+    assert_eq!(
+        shell_parse("'outer: x'"),
+        Ok(shell(("outer: x", 0..10), &[], None))
+    );
+    assert_eq!(
+        shell_parse("breakfast"),
+        Ok(shell(("breakfast", 0..9), &[], None))
+    );
+
+    // エラー
+    assert_eq!(
+        shell_parse("for x in %a { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoLoopVar, 4)
+    );
+    assert_eq!(
+        shell_parse("for %x of %a { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoIn, 7)
+    );
+    assert_eq!(
+        shell_parse("for %x in { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoIterator, 10)
+    );
+    assert_eq!(
+        shell_parse("while { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoCondition, 6)
+    );
+}
+
+#[test]
+fn function_test() {
+    let name = |name: &str, span| Spanned {
+        inner: name.to_string(),
+        span,
+    };
+    let ty = |ty, span| Spanned { inner: ty, span };
+
+    assert_eq!(
+        shell_parse("fn greet(%name: string, %n: int = 3) { echo %n }"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Function(Function {
+                    name: name("greet", 3..8),
+                    params: vec![
+                        Param {
+                            name: name("name", 9..14),
+                            ty: ty(Type::String, 16..22),
+                            default: None,
+                        },
+                        Param {
+                            name: name("n", 24..26),
+                            ty: ty(Type::Int, 28..31),
+                            default: Some(Spanned {
+                                inner: Expr::Int(3),
+                                span: 34..35
+                            }),
+                        },
+                    ],
+                    block: Block {
+                        commands: vec![(
+                            Statement::Command(Command {
+                                env: vec![],
+                                name: literal(("echo", 39..43)),
+                                args: vec![shell_var(("n", 44..46))],
+                                redirects: vec![],
+                            }),
+                            None,
+                        )],
+                    },
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    // 引数は複数行に分けて書ける
+    assert!(matches!(
+        shell_parse("fn f(\n  %a: int,\n  %rest: array<string>,\n) {}")
+            .unwrap()
+            .commands[0]
+            .0,
+        Statement::Function(Function { ref params, .. }) if params.len() == 2
+    ));
+
+    // return
+    assert_eq!(
+        shell_parse("fn f() { return }").unwrap().commands[0].0,
+        Statement::Function(Function {
+            name: name("f", 3..4),
+            params: vec![],
+            block: Block {
+                commands: vec![(
+                    Statement::Return(Spanned {
+                        inner: None,
+                        span: 9..15
+                    }),
+                    None
+                )],
+            },
+        })
+    );
+    assert_eq!(
+        shell_parse("return (%a + 1)").unwrap().commands[0].0,
+        Statement::Return(Spanned {
+            inner: Some(Spanned {
+                inner: Word::Expr(Box::new(Spanned {
+                    inner: Expr::Binary(
+                        Box::new(Spanned {
+                            inner: Expr::Word(Word::ShellVar("a".to_string())),
+                            span: 8..10
+                        }),
+                        BinaryOp::Add,
+                        Box::new(Spanned {
+                            inner: Expr::Int(1),
+                            span: 13..14
+                        }),
+                    ),
+                    span: 8..14
+                })),
+                span: 7..15
+            }),
+            span: 0..15
+        })
+    );
+    // 関数名で始まるだけならただのコマンド
+    assert_eq!(shell_parse("fnord"), Ok(shell(("fnord", 0..5), &[], None)));
+
+    // エラー
+    assert_eq!(
+        shell_parse("fn f { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoParams, 5)
+    );
+    assert_eq!(
+        shell_parse("fn f(%a) { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoParamType, 7)
+    );
+    assert_eq!(
+        shell_parse("fn f(%a: int").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoEndParen, 12)
+    );
+    assert_eq!(
+        shell_parse("fn f(%a: array<int>, %b: int) { }")
+            .unwrap_err()
+            .into_inner(),
+        parse_error(ParseErrorKind::ArrayParamNotLast, 9)
+    );
+    assert_eq!(
+        shell_parse("fn f()").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoBlock, 6)
+    );
+}
+
+#[test]
+fn match_test() {
+    let pattern = |pattern, span| Spanned {
+        inner: pattern,
+        span,
+    };
+    let echo = |span: Span| Block {
+        commands: vec![(
+            Statement::Command(Command {
+                env: vec![],
+                name: literal(("echo", span)),
+                args: vec![],
+                redirects: vec![],
+            }),
+            None,
+        )],
+    };
+    let arms = |input| match shell_parse(input).unwrap().commands[0].0 {
+        Statement::Match(ref match_) => match_.arms.clone(),
+        ref statement => panic!("{statement:?}"),
+    };
+
+    assert_eq!(
+        shell_parse("match %x {\n  \"a\" | b* => echo\n  _ => { echo },\n}"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Match(Match {
+                    value: shell_var(("x", 6..8)),
+                    arms: vec![
+                        MatchArm {
+                            patterns: vec![
+                                pattern(
+                                    Pattern::String("a".to_string()),
+                                    13..16
+                                ),
+                                pattern(
+                                    Pattern::Glob("b*".to_string()),
+                                    19..21
+                                ),
+                            ],
+                            block: echo(25..29),
+                        },
+                        MatchArm {
+                            patterns: vec![pattern(Pattern::Wildcard, 32..33)],
+                            block: echo(39..43),
+                        },
+                    ],
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    // 1行に書いた腕は;で区切る
+    assert_eq!(
+        arms("match %o { some(%v) => echo; none => echo }"),
+        vec![
+            MatchArm {
+                patterns: vec![pattern(
+                    Pattern::Some(Box::new(pattern(
+                        Pattern::Bind("v".to_string()),
+                        16..18
+                    ))),
+                    11..19
+                )],
+                block: echo(23..27),
+            },
+            MatchArm {
+                patterns: vec![pattern(Pattern::None, 29..33)],
+                block: echo(37..41),
+            },
+        ]
+    );
+    assert_eq!(
+        arms("match %n { -1 | 10 => echo; true => echo; 1a => echo }")
+            .into_iter()
+            .flat_map(|arm| arm.patterns)
+            .map(|pattern| pattern.inner)
+            .collect::<Vec<_>>(),
+        vec![
+            Pattern::Int(-1),
+            Pattern::Int(10),
+            Pattern::Bool(true),
+            Pattern::Glob("1a".to_string()),
+        ]
+    );
+    assert_eq!(arms("match %x {\n  # comment\n}"), vec![]);
+
+    // エラー
+    assert_eq!(
+        shell_parse("match { }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoMatchValue, 6)
+    );
+    assert_eq!(
+        shell_parse("match %x { a echo }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoFatArrow, 13)
+    );
+    assert_eq!(
+        shell_parse("match %x { a => }").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoArmBody, 16)
+    );
+    // 腕はカンマでは区切れない
+    assert_eq!(
+        shell_parse("match a.rs { *.rs => echo rust, *.txt => echo text }")
+            .unwrap_err()
+            .into_inner(),
+        parse_error(ParseErrorKind::FatArrowInArm, 38)
+    );
+    assert_eq!(
+        shell_parse("match a.rs { *.rs => echo '=>' }").map(|_| ()),
+        Ok(())
+    );
+    assert_eq!(
+        shell_parse("match %x { a | => echo }")
+            .unwrap_err()
+            .into_inner(),
+        parse_error(ParseErrorKind::NoPattern, 15)
+    );
+    assert_eq!(
+        shell_parse("match %x { some(1 => echo }")
+            .unwrap_err()
+            .into_inner(),
+        parse_error(ParseErrorKind::NoEndParen, 18)
+    );
+}
+
+#[test]
+fn group_test() {
+    let echo = |arg: SpannedInput, span: Span| {
+        Statement::Command(simple_command(("echo", span), &[arg]))
+    };
+    let group = |input| match shell_parse(input).unwrap().commands[0].0 {
+        Statement::Group(ref group) => group.clone(),
+        ref statement => panic!("{statement:?}"),
+    };
+
+    assert_eq!(
+        shell_parse("{ echo a; echo b } > out"),
+        Ok(ShellCommand {
+            commands: vec![(
+                Statement::Group(Group {
+                    block: Block {
+                        commands: vec![
+                            (echo(("a", 7..8), 2..6), Some(Pipe::Split)),
+                            (echo(("b", 15..16), 10..14), None),
+                        ],
+                    },
+                    subshell: false,
+                    redirects: vec![Spanned {
+                        inner: Redirect {
+                            fd: 1,
+                            target: RedirectTarget::Write(literal((
+                                "out",
+                                21..24
+                            ))),
+                        },
+                        span: 19..24
+                    }],
+                }),
+                None,
+            )],
+            comment: None,
+        })
+    );
+    assert_eq!(
+        group("subshell {\n  echo a\n}"),
+        Group {
+            block: Block {
+                commands: vec![(
+                    echo(("a", 18..19), 13..17),
+                    Some(Pipe::Split),
+                )],
+            },
+            subshell: true,
+            redirects: vec![],
+        }
+    );
+    // パイプでつなげる
+    let commands = shell_parse("echo a | { cat } | cat").unwrap().commands;
+    assert!(matches!(
+        commands[..],
+        [
+            (Statement::Command(_), Some(Pipe::Pipe)),
+            (Statement::Group(_), Some(Pipe::Pipe)),
+            (Statement::Command(_), None),
+        ]
+    ));
+    // {が続かなければただのコマンド
+    assert_eq!(
+        shell_parse("subshell"),
+        Ok(shell(("subshell", 0..8), &[], None))
+    );
+
+    // エラー
+    assert_eq!(
+        shell_parse("{ echo a").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoEndBrace, 8)
+    );
+    assert_eq!(
+        shell_parse("{ echo a } >").unwrap_err().into_inner(),
+        parse_error(ParseErrorKind::NoRedirectTarget, 12)
+    );
+}
@@ -0,0 +1,142 @@
+use crate::{
+    exec::{self, Shell},
+    parse::{
+        self, ShellCommand,
+        error::{ParseError, Source},
+    },
+};
+
+/// まとめて解析した入力
+pub struct Parsed {
+    /// エラー表示に使う元の文字列
+    pub source: String,
+    /// `source`の最初の行番号
+    pub line: usize,
+    pub result: Result<ShellCommand, ParseError>,
+}
+impl Parsed {
+    /// ファイル名`file`を付けたエラー表示用の入力
+    pub fn located<'a>(&'a self, file: Option<&'a str>) -> Source<'a> {
+        Source {
+            text: &self.source,
+            origin: file.map(|file| (file, self.line)),
+        }
+    }
+}
+
+/// 括弧やコメントが閉じるまで行をまとめる
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: String,
+    /// これまでに受け取った行数
+    lines: usize,
+    /// `pending`の最初の行番号
+    start: usize,
+}
+impl LineBuffer {
+    /// 続きの行を待っているか
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+    /// 1行を加えて、まとまれば解析した結果を返す
+    pub fn push(&mut self, line: &str) -> Option<Parsed> {
+        self.lines += 1;
+        if !self.is_pending() {
+            self.start = self.lines;
+        }
+        self.pending += line;
+        let source = std::mem::take(&mut self.pending);
+        let result = match parse::parse_shell_command(&source) {
+            Ok(command) => Ok(command),
+            Err(e) if e.inner().is_incomplete(&source) => {
+                self.pending = source + "\n";
+                return None;
+            }
+            Err(e) => Err(e.into_inner()),
+        };
+        Some(Parsed {
+            source,
+            line: self.start,
+            result,
+        })
+    }
+    /// 閉じないまま入力が終わった場合はそのエラーを返す
+    pub fn finish(self) -> Option<Parsed> {
+        if !self.is_pending() {
+            return None;
+        }
+        let source = self.pending.trim_end_matches('\n').to_string();
+        let result =
+            parse::parse_shell_command(&source).map_err(|e| e.into_inner());
+        Some(Parsed {
+            source,
+            line: self.start,
+            result,
+        })
+    }
+}
+
+/// ソースを論理行ごとに解析する
+///
+/// 1行目の`#!`はシバンとして読み飛ばす
+fn parse_source(source: &str) -> Vec<Parsed> {
+    let mut buffer = LineBuffer::default();
+    let mut parsed = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        if i == 0 && line.starts_with("#!") {
+            buffer.lines += 1;
+            continue;
+        }
+        parsed.extend(buffer.push(line));
+    }
+    parsed.extend(buffer.finish());
+    parsed
+}
+
+/// ソースを実行して終了ステータスを返す
+///
+/// 構文エラーがあればそこで実行をやめる
+/// `file`はエラー表示に使うファイル名で、`exit`は`Err`で返す
+pub fn run_source(
+    shell: &mut Shell,
+    source: &str,
+    file: Option<&str>,
+) -> Result<i32, exec::Error> {
+    for parsed in parse_source(source) {
+        let command = match &parsed.result {
+            Ok(command) => command,
+            Err(e) => {
+                eprint!("{}", e.display(parsed.located(file)));
+                return Ok(2);
+            }
+        };
+        shell.execute(command, parsed.located(file))?;
+    }
+    Ok(shell.last_status())
+}
+
+/// ソースを実行せずに構文と型だけ検査して終了ステータスを返す
+///
+/// すべてのエラーを表示し、構文エラーがあれば2、型エラーがあれば1を返す
+pub fn check_source(shell: &Shell, source: &str, file: Option<&str>) -> i32 {
+    let mut checker = shell.checker();
+    let mut status = 0;
+    for parsed in parse_source(source) {
+        match &parsed.result {
+            Ok(command) => {
+                if let Err(e) = checker.check(command) {
+                    eprint!("{}", e.display(parsed.located(file)));
+                    status = status.max(1);
+                }
+            }
+            Err(e) => {
+                eprint!("{}", e.display(parsed.located(file)));
+                status = 2;
+            }
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod test;
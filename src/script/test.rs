@@ -0,0 +1,112 @@
+use super::*;
+
+#[test]
+fn line_buffer_test() {
+    let mut buffer = LineBuffer::default();
+
+    // 括弧が閉じるまで行をまとめる
+    assert!(buffer.push("echo (1 +").is_none());
+    assert!(buffer.is_pending());
+    let parsed = buffer.push("2)").unwrap();
+    assert_eq!(parsed.source, "echo (1 +\n2)");
+    assert!(parsed.result.is_ok());
+    assert!(!buffer.is_pending());
+
+    // ブロックは}が来るまでまとめる
+    assert!(buffer.push("if true {").is_none());
+    assert!(buffer.push("  echo a").is_none());
+    let parsed = buffer.push("} else { echo b }").unwrap();
+    assert_eq!(parsed.source, "if true {\n  echo a\n} else { echo b }");
+    assert!(parsed.result.is_ok());
+    assert_eq!(parsed.line, 3);
+
+    // 構文エラーはまとめずにすぐ返す
+    let parsed = buffer.push("echo )").unwrap();
+    assert!(parsed.result.is_err());
+    assert!(!buffer.is_pending());
+
+    // 閉じないまま終わった入力はエラー
+    assert!(buffer.push("#| comment").is_none());
+    let parsed = buffer.finish().unwrap();
+    assert_eq!(parsed.source, "#| comment");
+    assert!(parsed.result.is_err());
+    assert!(LineBuffer::default().finish().is_none());
+}
+
+#[test]
+fn parse_source_test() {
+    let sources = |source: &str| {
+        parse_source(source)
+            .into_iter()
+            .map(|parsed| parsed.source)
+            .collect::<Vec<_>>()
+    };
+
+    // シバンは1行目のみ
+    assert_eq!(
+        sources("#!/usr/bin/env asari\necho a\n#!b\n"),
+        vec!["echo a", "#!b"]
+    );
+    assert_eq!(
+        sources("%a = [\n  1,\n  2,\n]\necho %a"),
+        vec!["%a = [\n  1,\n  2,\n]", "echo %a"]
+    );
+    assert_eq!(sources("a\r\nb\r\n"), vec!["a", "b"]);
+}
+
+#[test]
+fn check_source_test() {
+    let check = |shell: &Shell, source: &str| check_source(shell, source, None);
+    let shell = Shell::new();
+
+    assert_eq!(check(&shell, "%a = (1)\necho (%a + 1)"), 0);
+    // 前の行の代入を引き継ぐ
+    assert_eq!(check(&shell, "%a = (1)\necho (%a + 1.5)"), 1);
+    assert_eq!(check(&shell, "echo (1 +\n1.5)\necho )"), 2);
+    // 実行はしない
+    let mut shell = Shell::new();
+    assert_eq!(check(&shell, "%a = x"), 0);
+    assert_eq!(run_source(&mut shell, "echo %a", None).ok(), Some(1));
+}
+
+#[test]
+fn run_source_test() {
+    let run = |shell: &mut Shell, source: &str| {
+        run_source(shell, source, None).unwrap_or_else(|e| e.status())
+    };
+    let mut shell = Shell::new();
+
+    assert_eq!(run(&mut shell, "%a = (1)\n%a = (%a + 1)"), 0);
+    assert_eq!(run(&mut shell, "echo (%a + 1.5)"), 1);
+    // 構文エラーがあればそこでやめる
+    assert_eq!(run(&mut shell, "%b = x\necho )\n%c = y"), 2);
+    assert_eq!(run(&mut shell, "echo %b %c"), 1);
+    // exitはErrで返す
+    assert!(matches!(
+        run_source(&mut shell, "exit 3\n%d = z", None),
+        Err(exec::Error::Exit(3))
+    ));
+    assert_eq!(run(&mut shell, "echo %d"), 1);
+}
+
+#[test]
+fn located_display_test() {
+    let parsed = parse_source("#!/bin/asari\necho a\n%a = [\n  1,\n  )");
+    assert_eq!(
+        parsed.iter().map(|parsed| parsed.line).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    let Err(e) = &parsed[1].result
+    else {
+        panic!("構文エラーになっていません");
+    };
+    assert_eq!(
+        e.display(parsed[1].located(Some("config.asr"))),
+        "config.asr:5:3\n%a = [   1,   )\n              ^ 式が必要です\n"
+    );
+    // ファイル名がなければ位置は付けない
+    assert_eq!(
+        e.display(parsed[1].source.as_str()),
+        "%a = [   1,   )\n              ^ 式が必要です\n"
+    );
+}
@@ -0,0 +1,130 @@
+use std::{ffi::OsString, fmt::Display, path::PathBuf};
+
+/// 実行時の値
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    String(String),
+    Int(i64),
+    Float(f64),
+    Path(PathBuf),
+    Array(Vec<Value>),
+    Option(Option<Box<Value>>),
+}
+impl Value {
+    pub fn some(value: Value) -> Self {
+        Value::Option(Some(Box::new(value)))
+    }
+    pub fn none() -> Self {
+        Value::Option(None)
+    }
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::Unit => Type::Unit,
+            Value::Bool(_) => Type::Bool,
+            Value::String(_) => Type::String,
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::Path(_) => Type::Path,
+            Value::Array(values) => Type::Array(Box::new(
+                values.first().map(Value::ty).unwrap_or(Type::Unknown),
+            )),
+            Value::Option(value) => Type::Option(Box::new(
+                value
+                    .as_ref()
+                    .map(|value| value.ty())
+                    .unwrap_or(Type::Unknown),
+            )),
+        }
+    }
+    /// コマンドの引数に展開する
+    ///
+    /// 単語分割は行わず、配列は要素ごとに別の引数になる
+    pub fn into_args(self, args: &mut Vec<OsString>) {
+        match self {
+            Value::Unit => {}
+            Value::Bool(bool) => args.push(bool.to_string().into()),
+            Value::String(string) => args.push(string.into()),
+            Value::Int(int) => args.push(int.to_string().into()),
+            Value::Float(float) => args.push(format!("{float:?}").into()),
+            Value::Path(path) => args.push(path.into()),
+            Value::Array(values) => {
+                for value in values {
+                    value.into_args(args);
+                }
+            }
+            Value::Option(Some(value)) => value.into_args(args),
+            Value::Option(None) => {}
+        }
+    }
+}
+
+/// 値の型
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Type {
+    String,
+    Int,
+    Float,
+    Bool,
+    Path,
+    Unit,
+    Array(Box<Type>),
+    Option(Box<Type>),
+    /// 空の配列やnoneの要素など、まだ決まっていない型
+    Unknown,
+}
+impl Type {
+    /// 要素の型を除いた型の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::String => "string",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Bool => "bool",
+            Type::Path => "path",
+            Type::Unit => "unit",
+            Type::Array(_) => "array",
+            Type::Option(_) => "option",
+            Type::Unknown => "unknown",
+        }
+    }
+    /// `as`でこの型から`to`型に変換できるか
+    pub fn can_cast(&self, to: &Type) -> bool {
+        use Type::*;
+        match (self, to) {
+            (Unknown, _) | (_, Unknown) => true,
+            (from, to) if to.accepts(from) => true,
+            (Int, Float | String)
+            | (Float, Int | String)
+            | (Bool, Int | String)
+            | (String, Int | Float | Bool | Path)
+            | (Path, String) => true,
+            _ => false,
+        }
+    }
+    /// `other`の値をこの型の変数に代入できるか
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (_, Type::Unknown) | (Type::Unknown, _) => true,
+            (Type::Array(a), Type::Array(b))
+            | (Type::Option(a), Type::Option(b)) => a.accepts(b),
+            (a, b) => a == b,
+        }
+    }
+}
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::String => write!(f, "string"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Path => write!(f, "path"),
+            Type::Unit => write!(f, "unit"),
+            Type::Array(ty) => write!(f, "array<{ty}>"),
+            Type::Option(ty) => write!(f, "option<{ty}>"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}